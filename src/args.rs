@@ -1,3 +1,7 @@
+use crate::client::HttpSettings;
+use crate::render::Output;
+use chrono::NaiveDate;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -9,4 +13,325 @@ pub struct Opts {
     // Which pipe to get
     #[structopt(long = "pipe", short = "p")]
     pub pipe_id: Option<usize>,
+    // How to print results: pretty, plain, table, csv, json or yaml
+    #[structopt(
+        long = "output",
+        short = "o",
+        default_value = "pretty",
+        raw(global = "true")
+    )]
+    pub output: Output,
+    // Which stored profile to use instead of the current one
+    #[structopt(long = "profile", env = "PIPE_CLI_PROFILE", raw(global = "true"))]
+    pub profile: Option<String>,
+    // Read the API key from this file instead of the profile
+    #[structopt(long = "token-file", parse(from_os_str), raw(global = "true"))]
+    pub token_file: Option<PathBuf>,
+    // Read the API key from stdin instead of the profile
+    #[structopt(long = "token-stdin", raw(global = "true"))]
+    pub token_stdin: bool,
+    // GraphQL endpoint to use instead of the profile's
+    #[structopt(long = "endpoint", env = "PIPE_CLI_ENDPOINT", raw(global = "true"))]
+    pub endpoint: Option<String>,
+    #[structopt(flatten)]
+    pub http: HttpOpts,
+    // Report requests on stderr, -vv shows what was sent and received
+    #[structopt(
+        short = "v",
+        long = "verbose",
+        parse(from_occurrences),
+        raw(global = "true")
+    )]
+    pub verbose: u8,
+    // Write every request and response to this file as JSON lines
+    #[structopt(long = "trace-file", parse(from_os_str), raw(global = "true"))]
+    pub trace_file: Option<PathBuf>,
+    // Answer from a trace file, or a directory of them, instead of the network
+    #[structopt(long = "replay", parse(from_os_str), raw(global = "true"))]
+    pub replay: Option<PathBuf>,
+    // Run a single command and exit instead of opening the menus
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// HTTP settings that take precedence over the profile's.
+#[derive(Debug, StructOpt)]
+pub struct HttpOpts {
+    // Send every request through this proxy, like http://proxy:3128
+    #[structopt(long = "proxy", raw(global = "true"))]
+    pub proxy: Option<String>,
+    // PEM certificate to trust on top of the system ones
+    #[structopt(long = "ca-bundle", parse(from_os_str), raw(global = "true"))]
+    pub ca_bundle: Option<PathBuf>,
+    // Seconds to wait for a response
+    #[structopt(long = "timeout", raw(global = "true"))]
+    pub timeout: Option<u64>,
+    #[structopt(long = "user-agent", raw(global = "true"))]
+    pub user_agent: Option<String>,
+    // How many times a failed request may be sent again, defaults to 3
+    #[structopt(long = "retries", raw(global = "true"))]
+    pub retries: Option<u32>,
+    // Longest Retry-After in seconds to wait for before giving up, defaults
+    // to 120
+    #[structopt(long = "max-wait", raw(global = "true"))]
+    pub max_wait: Option<u64>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    #[structopt(name = "pipe", about = "Query a pipe")]
+    Pipe(PipeCommand),
+    #[structopt(name = "org", about = "Query an organization")]
+    Org(OrgCommand),
+    #[structopt(name = "card", about = "Query a card")]
+    Card(CardCommand),
+    #[structopt(name = "board", about = "Open a pipe as a full screen kanban board")]
+    Board { id: usize },
+    #[structopt(name = "profile", about = "Manage the stored profiles")]
+    Profile(ProfileCommand),
+    #[structopt(name = "auth", about = "Manage the API key of a profile")]
+    Auth(AuthCommand),
+    #[structopt(name = "webhook", about = "React to Pipefy webhooks")]
+    Webhook(WebhookCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum PipeCommand {
+    #[structopt(name = "phases", about = "List the phases of a pipe")]
+    Phases { id: usize },
+    #[structopt(name = "cards", about = "List the cards of a pipe")]
+    Cards {
+        id: usize,
+        // Stop after this many cards
+        #[structopt(long = "limit")]
+        limit: Option<usize>,
+        // How many cards to request per page
+        #[structopt(
+            long = "page-size",
+            default_value = "50",
+            parse(try_from_str = "parse_page_size")
+        )]
+        page_size: usize,
+    },
+    #[structopt(
+        name = "watch",
+        about = "Print the changes to the cards of a pipe as they happen, as JSON lines with -o json"
+    )]
+    Watch {
+        id: usize,
+        // Seconds between two looks at the pipe
+        #[structopt(
+            long = "interval",
+            default_value = "30",
+            parse(try_from_str = "parse_interval")
+        )]
+        interval: u64,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum OrgCommand {
+    #[structopt(name = "pipes", about = "List the pipes of an organization")]
+    Pipes { id: usize },
+    #[structopt(name = "members", about = "List the members of an organization")]
+    Members { id: usize },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CardCommand {
+    #[structopt(name = "show", about = "Show a card")]
+    Show { id: usize },
+    #[structopt(name = "create", about = "Create a card in a pipe")]
+    Create {
+        // Pipe the card goes into
+        #[structopt(long = "pipe")]
+        pipe_id: usize,
+        #[structopt(long = "title")]
+        title: Option<String>,
+        // Start form values, as field=value where field is the id or the label
+        #[structopt(long = "field", parse(try_from_str = "parse_assignment"))]
+        fields: Vec<(String, String)>,
+        // Walk the start form with prompts instead
+        #[structopt(long = "interactive", short = "i")]
+        interactive: bool,
+    },
+    #[structopt(name = "move", about = "Move a card to another phase")]
+    Move {
+        id: usize,
+        // Destination phase, by id or by name
+        #[structopt(long = "to")]
+        to: String,
+    },
+    #[structopt(name = "search", about = "Find the cards of a pipe that match filters")]
+    Search {
+        #[structopt(long = "pipe")]
+        pipe_id: usize,
+        // Part of the title, ignoring case
+        #[structopt(long = "title")]
+        title: Option<String>,
+        // Phase id or name
+        #[structopt(long = "phase")]
+        phase: Option<String>,
+        // Label id or name, repeat to accept any of several
+        #[structopt(long = "label")]
+        labels: Vec<String>,
+        // Assignee id or name, repeat to accept any of several
+        #[structopt(long = "assignee")]
+        assignees: Vec<String>,
+        // Dates are YYYY-MM-DD and ranges include both ends
+        #[structopt(long = "created-from", parse(try_from_str = "parse_date"))]
+        created_from: Option<NaiveDate>,
+        #[structopt(long = "created-to", parse(try_from_str = "parse_date"))]
+        created_to: Option<NaiveDate>,
+        #[structopt(long = "updated-from", parse(try_from_str = "parse_date"))]
+        updated_from: Option<NaiveDate>,
+        #[structopt(long = "updated-to", parse(try_from_str = "parse_date"))]
+        updated_to: Option<NaiveDate>,
+        #[structopt(long = "due-from", parse(try_from_str = "parse_date"))]
+        due_from: Option<NaiveDate>,
+        #[structopt(long = "due-to", parse(try_from_str = "parse_date"))]
+        due_to: Option<NaiveDate>,
+        // Field value to match, as field=value where field is the id or the label
+        #[structopt(long = "field", parse(try_from_str = "parse_assignment"))]
+        fields: Vec<(String, String)>,
+        // Stop after this many matching cards
+        #[structopt(long = "limit")]
+        limit: Option<usize>,
+        // How many cards to request per page
+        #[structopt(
+            long = "page-size",
+            default_value = "50",
+            parse(try_from_str = "parse_page_size")
+        )]
+        page_size: usize,
+    },
+    #[structopt(name = "set-field", about = "Change the value of a card field")]
+    SetField {
+        id: usize,
+        // Field id or label
+        field: String,
+        // New value, lists are comma separated
+        value: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ProfileCommand {
+    #[structopt(name = "list", about = "List the stored profiles")]
+    List,
+    #[structopt(
+        name = "add",
+        about = "Add a profile, keeping the --endpoint and HTTP flags given"
+    )]
+    Add {
+        name: String,
+        // Organization offered by default in the menus
+        #[structopt(long = "org")]
+        default_org: Option<usize>,
+        // Pipe offered by default in the menus
+        #[structopt(long = "pipe")]
+        default_pipe: Option<usize>,
+    },
+    #[structopt(name = "remove", about = "Remove a profile")]
+    Remove { name: String },
+    #[structopt(name = "use", about = "Make a profile the current one")]
+    Use { name: String },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum AuthCommand {
+    #[structopt(name = "login", about = "Ask for an API key, check it and store it")]
+    Login,
+    #[structopt(name = "logout", about = "Forget the stored API key")]
+    Logout,
+    #[structopt(name = "status", about = "Show who the stored API key belongs to")]
+    Status,
+    #[structopt(
+        name = "rotate",
+        about = "Replace the API key with a new one of the same user"
+    )]
+    Rotate,
+    #[structopt(
+        name = "encrypt",
+        about = "Choose the secret the stored API keys are encrypted with"
+    )]
+    Encrypt {
+        // Ask for a passphrase instead of using a secret file
+        #[structopt(long = "passphrase")]
+        passphrase: bool,
+        // Secret file to use instead of secret.key in the local data dir
+        #[structopt(long = "secret-file", parse(from_os_str))]
+        secret_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum WebhookCommand {
+    #[structopt(
+        name = "serve",
+        about = "Receive webhooks on a local port, printing them and running hooks"
+    )]
+    Serve {
+        #[structopt(long = "port", default_value = "8080")]
+        port: u16,
+        // Address to listen on, 0.0.0.0 to accept other machines
+        #[structopt(long = "bind", default_value = "127.0.0.1")]
+        bind: String,
+        // Shell command to run, as action=command where * is any action.
+        // It gets the event as JSON on stdin and PIPE_CLI_ACTION,
+        // PIPE_CLI_CARD_ID and PIPE_CLI_CARD_TITLE in its environment
+        #[structopt(long = "on", parse(try_from_str = "parse_assignment"))]
+        hooks: Vec<(String, String)>,
+        // Fetch the whole card from the API, webhooks only carry its id and title
+        #[structopt(long = "fetch")]
+        fetch: bool,
+        // Only accept webhooks whose X-Pipe-Cli-Secret header is this value,
+        // set it in the headers of the webhook on Pipefy
+        #[structopt(long = "secret")]
+        secret: Option<String>,
+    },
+}
+
+impl HttpOpts {
+    pub fn settings(&self) -> HttpSettings {
+        HttpSettings {
+            proxy: self.proxy.clone(),
+            ca_bundle: self.ca_bundle.clone(),
+            timeout: self.timeout,
+            user_agent: self.user_agent.clone(),
+            retries: self.retries,
+            max_wait: self.max_wait,
+        }
+    }
+}
+
+fn parse_page_size(size: &str) -> Result<usize, String> {
+    match size.parse() {
+        Ok(0) => Err("The page size must be at least 1".to_string()),
+        Ok(size) => Ok(size),
+        Err(_) => Err(format!("Expected a number of cards, got {}", size)),
+    }
+}
+
+fn parse_interval(interval: &str) -> Result<u64, String> {
+    match interval.parse() {
+        Ok(0) => Err("The interval must be at least 1 second".to_string()),
+        Ok(interval) => Ok(interval),
+        Err(_) => Err(format!("Expected a number of seconds, got {}", interval)),
+    }
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("Expected a YYYY-MM-DD date, got {}", date))
+}
+
+fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    let mut parts = assignment.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("Expected name=value, got {}", assignment)),
+    }
 }
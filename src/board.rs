@@ -0,0 +1,396 @@
+use crate::client::PipefyClient;
+use crate::error::PipefyError;
+use crate::graphql::{Card, Phase, PhaseRef};
+use crate::picker::{self, Pickable};
+use std::error::Error;
+use std::io;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use tui::backend::{Backend, TermionBackend};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use tui::{Frame, Terminal};
+
+const MAX_COLUMNS: usize = 5;
+const HELP: &str = "←↓↑→ navigate  Enter details  m move  / filter  r refresh  q quit";
+
+/// A pipe laid out as a kanban board, one column per phase.
+struct Board<'a> {
+    client: &'a PipefyClient,
+    pipe_id: usize,
+    name: String,
+    columns: Vec<Column>,
+    column: usize,
+    row: usize,
+    filter: String,
+    detail: bool,
+    mode: Mode,
+    status: Option<String>,
+}
+
+struct Column {
+    phase: Phase,
+    cards: Vec<Card>,
+}
+
+enum Mode {
+    Browse,
+    Filter,
+    Move {
+        targets: Vec<PhaseRef>,
+        selected: usize,
+    },
+}
+
+/// Opens the board of a pipe until the user quits it.
+pub fn run(client: &PipefyClient, pipe_id: usize) -> Result<(), Box<Error>> {
+    if !atty::is(atty::Stream::Stdout) || !atty::is(atty::Stream::Stdin) {
+        return Err("The board needs a terminal".into());
+    }
+    let mut board = Board::new(client, pipe_id);
+    board.load()?;
+    let stdout = AlternateScreen::from(io::stdout().into_raw_mode()?);
+    let mut terminal = Terminal::new(TermionBackend::new(stdout))?;
+    terminal.hide_cursor()?;
+    let mut keys = io::stdin().keys();
+    loop {
+        terminal.draw(|f| board.draw(f))?;
+        let key = match keys.next() {
+            Some(key) => key?,
+            None => break,
+        };
+        if !board.handle(key) {
+            break;
+        }
+    }
+    terminal.show_cursor()?;
+    Ok(())
+}
+
+impl<'a> Board<'a> {
+    fn new(client: &'a PipefyClient, pipe_id: usize) -> Board<'a> {
+        Board {
+            client,
+            pipe_id,
+            name: String::new(),
+            columns: Vec::new(),
+            column: 0,
+            row: 0,
+            filter: String::new(),
+            detail: false,
+            mode: Mode::Browse,
+            status: None,
+        }
+    }
+
+    fn load(&mut self) -> Result<(), PipefyError> {
+        let pipe = self.client.pipe(self.pipe_id)?;
+        let cards = self.client.all_pipe_cards(self.pipe_id)?;
+        self.name = pipe.name;
+        self.columns = pipe
+            .phases
+            .into_iter()
+            .map(|phase| {
+                let cards = cards
+                    .iter()
+                    .filter(|card| {
+                        card.current_phase
+                            .as_ref()
+                            .map_or(false, |current| current.id == phase.id)
+                    })
+                    .cloned()
+                    .collect();
+                Column { phase, cards }
+            })
+            .collect();
+        self.column = self.column.min(self.columns.len().saturating_sub(1));
+        self.clamp_row();
+        Ok(())
+    }
+
+    fn refresh(&mut self) {
+        self.status = Some(match self.load() {
+            Ok(()) => "Refreshed".to_string(),
+            Err(e) => e.to_string(),
+        });
+    }
+
+    /// The cards of a column that pass the filter bar.
+    fn visible(&self, column: usize) -> Vec<&Card> {
+        self.columns.get(column).map_or(Vec::new(), |column| {
+            column
+                .cards
+                .iter()
+                .filter(|card| picker::score(&card.haystack(), &self.filter).is_some())
+                .collect()
+        })
+    }
+
+    fn selected_card(&self) -> Option<&Card> {
+        self.visible(self.column).get(self.row).cloned()
+    }
+
+    fn clamp_row(&mut self) {
+        self.row = self
+            .row
+            .min(self.visible(self.column).len().saturating_sub(1));
+    }
+
+    /// Reacts to a key, returning false once the user wants to leave.
+    fn handle(&mut self, key: Key) -> bool {
+        self.status = None;
+        match self.mode {
+            Mode::Filter => {
+                match key {
+                    Key::Char('\n') => self.mode = Mode::Browse,
+                    Key::Esc => {
+                        self.filter.clear();
+                        self.mode = Mode::Browse;
+                    }
+                    Key::Backspace => {
+                        self.filter.pop();
+                    }
+                    Key::Char(typed) => self.filter.push(typed),
+                    _ => (),
+                }
+                self.row = 0;
+            }
+            Mode::Move {
+                ref targets,
+                selected,
+            } => match key {
+                Key::Up | Key::Char('k') => {
+                    self.mode = Mode::Move {
+                        targets: targets.clone(),
+                        selected: selected.saturating_sub(1),
+                    }
+                }
+                Key::Down | Key::Char('j') => {
+                    self.mode = Mode::Move {
+                        targets: targets.clone(),
+                        selected: (selected + 1).min(targets.len() - 1),
+                    }
+                }
+                Key::Char('\n') => {
+                    let destination = targets[selected].clone();
+                    self.mode = Mode::Browse;
+                    self.move_selected(&destination);
+                }
+                Key::Esc | Key::Char('q') => self.mode = Mode::Browse,
+                _ => (),
+            },
+            Mode::Browse => match key {
+                Key::Char('q') | Key::Esc | Key::Ctrl('c') => return false,
+                Key::Left | Key::Char('h') => {
+                    self.column = self.column.saturating_sub(1);
+                    self.clamp_row();
+                }
+                Key::Right | Key::Char('l') => {
+                    self.column = (self.column + 1).min(self.columns.len().saturating_sub(1));
+                    self.clamp_row();
+                }
+                Key::Up | Key::Char('k') => self.row = self.row.saturating_sub(1),
+                Key::Down | Key::Char('j') => {
+                    self.row += 1;
+                    self.clamp_row();
+                }
+                Key::Char('\n') => self.detail = !self.detail,
+                Key::Char('m') => self.start_move(),
+                Key::Char('r') => self.refresh(),
+                Key::Char('/') => self.mode = Mode::Filter,
+                _ => (),
+            },
+        }
+        true
+    }
+
+    fn start_move(&mut self) {
+        let card_id = match self.selected_card() {
+            Some(card) => card.id,
+            None => return,
+        };
+        match self.client.move_targets(card_id) {
+            Ok(moves) => {
+                if moves.cards_can_be_moved_to_phases.is_empty() {
+                    self.status =
+                        Some(format!("Cards in {} can not be moved anywhere", moves.name));
+                } else {
+                    self.mode = Mode::Move {
+                        targets: moves.cards_can_be_moved_to_phases,
+                        selected: 0,
+                    };
+                }
+            }
+            Err(e) => self.status = Some(e.to_string()),
+        }
+    }
+
+    fn move_selected(&mut self, destination: &PhaseRef) {
+        let card_id = match self.selected_card() {
+            Some(card) => card.id,
+            None => return,
+        };
+        match self.client.move_card(card_id, destination.id) {
+            Ok(card) => {
+                self.refresh();
+                self.status = Some(format!("Moved #{} to {}", card.id, destination.name));
+            }
+            Err(e) => self.status = Some(e.to_string()),
+        }
+    }
+
+    fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(1),
+                    Constraint::Min(3),
+                    Constraint::Length(1),
+                ]
+                .as_ref(),
+            )
+            .split(f.size());
+        self.draw_header(f, rows[0]);
+        let body = if self.detail {
+            Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(65), Constraint::Percentage(35)].as_ref())
+                .split(rows[1])
+        } else {
+            vec![rows[1]]
+        };
+        self.draw_columns(f, body[0]);
+        if let Some(area) = body.get(1) {
+            self.draw_detail(f, *area);
+        }
+        if let Mode::Move {
+            ref targets,
+            selected,
+        } = self.mode
+        {
+            self.draw_move(f, targets, selected);
+        }
+        let footer = self.status.as_ref().map_or(HELP, |status| status);
+        f.render_widget(
+            Paragraph::new(Span::styled(footer, Style::default().fg(Color::DarkGray))),
+            rows[2],
+        );
+    }
+
+    fn draw_header<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let mut spans = vec![Span::styled(
+            self.name.to_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        match self.mode {
+            Mode::Filter => {
+                spans.push(Span::raw("  / "));
+                spans.push(Span::styled(
+                    format!("{}▏", self.filter),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            _ if !self.filter.is_empty() => {
+                spans.push(Span::raw(format!("  filter: {}", self.filter)));
+            }
+            _ => (),
+        }
+        f.render_widget(Paragraph::new(Spans::from(spans)), area);
+    }
+
+    fn draw_columns<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let shown = self.columns.len().min(MAX_COLUMNS);
+        if shown == 0 {
+            f.render_widget(Paragraph::new("This pipe has no phases"), area);
+            return;
+        }
+        let first = (self.column + 1).saturating_sub(shown);
+        let constraints = vec![Constraint::Ratio(1, shown as u32); shown];
+        let areas = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(constraints)
+            .split(area);
+        for (offset, area) in areas.into_iter().enumerate() {
+            let index = first + offset;
+            let cards = self.visible(index);
+            let items: Vec<ListItem> = cards
+                .iter()
+                .map(|card| {
+                    let assignees = card
+                        .assignees
+                        .iter()
+                        .map(|assignee| assignee.name.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    ListItem::new(vec![
+                        Spans::from(Span::styled(
+                            card.title.to_string(),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        )),
+                        Spans::from(Span::styled(
+                            format!("#{} {}", card.id, assignees),
+                            Style::default().fg(Color::DarkGray),
+                        )),
+                    ])
+                })
+                .collect();
+            let border = if index == self.column {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            let phase = &self.columns[index].phase;
+            let block = Block::default()
+                .borders(Borders::ALL)
+                .border_style(border)
+                .title(format!("{} ({})", phase.name, cards.len()));
+            let list = List::new(items)
+                .block(block)
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            let mut state = ListState::default();
+            if index == self.column && !cards.is_empty() {
+                state.select(Some(self.row));
+            }
+            f.render_stateful_widget(list, area, &mut state);
+        }
+    }
+
+    fn draw_detail<B: Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let (title, text) = match self.selected_card() {
+            Some(card) => (format!("#{} {}", card.id, card.title), card.preview()),
+            None => ("No card".to_string(), String::new()),
+        };
+        let detail = Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .wrap(Wrap { trim: false });
+        f.render_widget(detail, area);
+    }
+
+    fn draw_move<B: Backend>(&self, f: &mut Frame<B>, targets: &[PhaseRef], selected: usize) {
+        let size = f.size();
+        let width = size.width.min(40);
+        let height = (targets.len() as u16 + 2).min(size.height);
+        let area = Rect::new(
+            (size.width - width) / 2,
+            (size.height - height) / 2,
+            width,
+            height,
+        );
+        let items: Vec<ListItem> = targets
+            .iter()
+            .map(|target| ListItem::new(target.name.to_string()))
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title("Move to"))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default();
+        state.select(Some(selected));
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(list, area, &mut state);
+    }
+}
@@ -0,0 +1,358 @@
+use crate::args::{
+    AuthCommand, CardCommand, Command, OrgCommand, PipeCommand, ProfileCommand, WebhookCommand,
+};
+use crate::board;
+use crate::client::{Endpoint, HttpSettings, PipefyClient};
+use crate::config::{self, Config, ConfigError, Profile};
+use crate::error::PipefyError;
+use crate::forms;
+use crate::graphql::{FieldValue, FormField};
+use crate::login;
+use crate::render::{self, Output};
+use crate::search::{CardFilter, DateRange};
+use crate::secret::KeySource;
+use crate::serve;
+use crate::user::User;
+use crate::watch::Snapshot;
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+pub fn run(client: &PipefyClient, output: Output, command: Command) -> Result<(), Box<Error>> {
+    match command {
+        Command::Pipe(PipeCommand::Phases { id }) => {
+            let pipe = client.pipe(id)?;
+            render::list(output, "Phases", &pipe.phases)
+        }
+        Command::Pipe(PipeCommand::Cards {
+            id,
+            limit,
+            page_size,
+        }) => {
+            let pages = client.pipe_cards(id, page_size, limit);
+            render::stream(output, "Cards", pages)
+        }
+        Command::Pipe(PipeCommand::Watch { id, interval }) => watch(client, output, id, interval),
+        Command::Board { id } => board::run(client, id),
+        Command::Org(OrgCommand::Pipes { id }) => {
+            let pipes = client.organization_pipes(id)?;
+            render::list(output, "Pipes", &pipes)
+        }
+        Command::Org(OrgCommand::Members { id }) => {
+            let organization = client.organization(id)?;
+            render::list(output, "Members", &organization.members)
+        }
+        Command::Card(CardCommand::Show { id }) => {
+            let card = client.card(id)?;
+            render::one(output, "Card", &card)
+        }
+        Command::Card(CardCommand::Create {
+            pipe_id,
+            title,
+            fields,
+            interactive,
+        }) => {
+            if interactive && (title.is_some() || !fields.is_empty()) {
+                return Err(
+                    "--interactive asks for the title and fields, drop --title and --field".into(),
+                );
+            }
+            let form_fields = client.start_form_fields(pipe_id)?;
+            let (title, values) = if interactive {
+                forms::fill_start_form(&form_fields)
+            } else {
+                (title, assign_fields(&form_fields, fields)?)
+            };
+            let card = client.create_card(pipe_id, title.as_ref().map(|t| t.as_str()), &values)?;
+            render::one(output, "Card", &card)
+        }
+        Command::Card(CardCommand::Search {
+            pipe_id,
+            title,
+            phase,
+            labels,
+            assignees,
+            created_from,
+            created_to,
+            updated_from,
+            updated_to,
+            due_from,
+            due_to,
+            fields,
+            limit,
+            page_size,
+        }) => {
+            let filter = CardFilter {
+                title,
+                phase,
+                labels,
+                assignees,
+                created: DateRange {
+                    from: created_from,
+                    to: created_to,
+                },
+                updated: DateRange {
+                    from: updated_from,
+                    to: updated_to,
+                },
+                due: DateRange {
+                    from: due_from,
+                    to: due_to,
+                },
+                fields,
+            };
+            let pages = client.search_cards(pipe_id, &filter, page_size, limit);
+            render::stream(output, "Cards", pages)
+        }
+        Command::Card(CardCommand::Move { id, to }) => {
+            let moves = client.move_targets(id)?;
+            let destination = match moves.destination(&to) {
+                Some(destination) => destination,
+                None => {
+                    let allowed = moves
+                        .cards_can_be_moved_to_phases
+                        .iter()
+                        .map(|phase| format!("{} ({})", phase.name, phase.id))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    return Err(format!(
+                        "Cards in {} can not be moved to {}, allowed phases: {}",
+                        moves.name, to, allowed
+                    )
+                    .into());
+                }
+            };
+            let card = client.move_card(id, destination.id)?;
+            render::one(output, "Card", &card)
+        }
+        Command::Card(CardCommand::SetField { id, field, value }) => {
+            let editable_fields = client.editable_fields(id)?;
+            let form_field = match editable_fields.iter().find(|f| f.is_named(&field)) {
+                Some(form_field) => form_field,
+                None => return Err(format!("Card {} has no field named {}", id, field).into()),
+            };
+            let new_value = form_field.parse_value(&value)?;
+            let card = client.update_card_field(id, &form_field.id, new_value)?;
+            render::one(output, "Card", &card)
+        }
+        Command::Webhook(WebhookCommand::Serve {
+            port,
+            bind,
+            hooks,
+            fetch,
+            secret,
+        }) => serve::run(
+            client,
+            output,
+            &bind,
+            port,
+            &hooks,
+            fetch,
+            secret.as_ref().map(String::as_str),
+        ),
+        Command::Profile(_) | Command::Auth(_) => {
+            unreachable!("profile and auth commands run before authenticating")
+        }
+    }
+}
+
+/// Polls a pipe until interrupted, printing what changed since the last look.
+/// A failed look is reported and retried at the next interval.
+fn watch(
+    client: &PipefyClient,
+    output: Output,
+    id: usize,
+    interval: u64,
+) -> Result<(), Box<Error>> {
+    let snapshot = |client: &PipefyClient| -> Result<Snapshot, PipefyError> {
+        let pipe = client.pipe(id)?;
+        let cards = client.all_pipe_cards(id)?;
+        Ok(Snapshot::new(&pipe, cards))
+    };
+    let mut previous = snapshot(client)?;
+    eprintln!(
+        "Watching {} cards every {}s, Ctrl-C to stop",
+        previous.card_count(),
+        interval
+    );
+    loop {
+        thread::sleep(Duration::from_secs(interval));
+        let current = match snapshot(client) {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        for event in previous.diff(&current) {
+            match output {
+                Output::Json => println!("{}", serde_json::to_string(&event)?),
+                _ => println!("{}", event),
+            }
+        }
+        previous = current;
+    }
+}
+
+/// `endpoint` and `http` come from the global flags and are kept by
+/// `profile add`.
+pub fn profile(
+    config: &mut Config,
+    output: Output,
+    endpoint: Option<String>,
+    http: HttpSettings,
+    command: ProfileCommand,
+) -> Result<(), Box<Error>> {
+    match command {
+        ProfileCommand::List => return render::list(output, "Profiles", &config.summaries()),
+        ProfileCommand::Add {
+            name,
+            default_org,
+            default_pipe,
+        } => {
+            if config.profiles.contains_key(&name) {
+                return Err(format!("There is already a profile named {}", name).into());
+            }
+            let profile = Profile {
+                endpoint,
+                default_org,
+                default_pipe,
+                http,
+                ..Profile::default()
+            };
+            config.profiles.insert(name, profile);
+        }
+        ProfileCommand::Remove { name } => {
+            config.profile(&name)?;
+            if name == config.current_profile {
+                return Err(
+                    format!("{} is the current profile, use another one first", name).into(),
+                );
+            }
+            config.profiles.remove(&name);
+        }
+        ProfileCommand::Use { name } => {
+            config.profile(&name)?;
+            config.current_profile = name;
+        }
+    }
+    config::store(config).map_err(ConfigError::from)?;
+    Ok(())
+}
+
+/// Checks the --field values against the start form, which must end up
+/// with every required field filled.
+fn assign_fields(
+    form_fields: &[FormField],
+    assignments: Vec<(String, String)>,
+) -> Result<Vec<FieldValue>, Box<Error>> {
+    let mut values = Vec::new();
+    for (name, value) in assignments {
+        let form_field = match form_fields
+            .iter()
+            .find(|form_field| form_field.is_named(&name))
+        {
+            Some(form_field) => form_field,
+            None => return Err(format!("The start form has no field named {}", name).into()),
+        };
+        let field_value = form_field.parse_value(&value)?;
+        if !field_value.is_null() {
+            values.push(FieldValue {
+                field_id: form_field.id.to_string(),
+                field_value,
+            });
+        }
+    }
+    let missing: Vec<&str> = form_fields
+        .iter()
+        .filter(|form_field| {
+            form_field.required && !values.iter().any(|value| value.field_id == form_field.id)
+        })
+        .map(|form_field| form_field.label.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("Required fields are missing: {}", missing.join(", ")).into());
+    }
+    Ok(values)
+}
+
+pub fn auth(
+    config: &mut Config,
+    profile_name: &str,
+    endpoint: &Endpoint,
+    external_token: Option<String>,
+    output: Output,
+    command: AuthCommand,
+) -> Result<(), Box<Error>> {
+    let mut profile = config.profile(profile_name)?.clone();
+    let ask_user = || -> User {
+        match external_token {
+            Some(api_key) => login::user_from_token(api_key, endpoint),
+            None => login::get_working_api_key(endpoint),
+        }
+    };
+    match command {
+        AuthCommand::Status => return render::one(output, "Auth", &profile.status(profile_name)),
+        AuthCommand::Login => {
+            let keyring = config.keyring()?;
+            let user = ask_user();
+            println!("Logged in to {} as {}", profile_name, user.info.name);
+            profile.user = Some(user);
+            profile.mark_validated();
+            profile = profile.locked(&keyring)?;
+        }
+        AuthCommand::Logout => {
+            profile.user = None;
+            profile.validated_at = None;
+            println!("Logged out of {}", profile_name);
+        }
+        AuthCommand::Rotate => {
+            let current_id = match &profile.user {
+                Some(current) => current.info.id,
+                None => {
+                    return Err(format!("{} is not logged in, use auth login", profile_name).into())
+                }
+            };
+            let keyring = config.keyring()?;
+            let user = ask_user();
+            if user.info.id != current_id {
+                return Err(format!(
+                    "That API key belongs to {}, use auth login to switch users",
+                    user.info.name
+                )
+                .into());
+            }
+            println!("Rotated the API key of {}", profile_name);
+            profile.user = Some(user);
+            profile.mark_validated();
+            profile = profile.locked(&keyring)?;
+        }
+        AuthCommand::Encrypt {
+            passphrase,
+            secret_file,
+        } => {
+            let keyring = config.keyring()?;
+            let profiles = config
+                .profiles
+                .iter()
+                .map(|(name, profile)| Ok((name.to_string(), profile.unlocked(&keyring)?)))
+                .collect::<Result<Vec<(String, Profile)>, ConfigError>>()?;
+            config.key_source = if passphrase {
+                KeySource::Passphrase
+            } else {
+                KeySource::SecretFile
+            };
+            config.secret_file = secret_file;
+            let keyring = config.new_keyring()?;
+            for (name, profile) in profiles {
+                config.profiles.insert(name, profile.locked(&keyring)?);
+            }
+            config::store(config).map_err(ConfigError::from)?;
+            println!("The stored API keys are now encrypted with the new secret");
+            return Ok(());
+        }
+    }
+    config.profiles.insert(profile_name.to_string(), profile);
+    config::store(config).map_err(ConfigError::from)?;
+    Ok(())
+}
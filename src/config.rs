@@ -0,0 +1,306 @@
+use crate::client::{HttpSettings, DEFAULT_ENDPOINT};
+use crate::secret::{self, KeySource, Keyring, SecretError};
+use crate::user::User;
+use chrono::{DateTime, Duration, Utc};
+use confy::ConfyError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::{error, fmt, io};
+
+const APP_NAME: &str = "pipe_cli";
+pub const DEFAULT_PROFILE: &str = "default";
+const SECRET_FILE_NAME: &str = "secret.key";
+/// How long a validated API key is trusted before checking it again.
+const VALIDATION_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Config {
+    pub current_profile: String,
+    #[serde(default)]
+    pub key_source: KeySource,
+    // Defaults to secret.key in the local data dir, away from the config it
+    // protects so that a copy of one does not carry the other
+    #[serde(default)]
+    pub secret_file: Option<PathBuf>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Profile {
+    pub endpoint: Option<String>,
+    pub default_org: Option<usize>,
+    pub default_pipe: Option<usize>,
+    pub validated_at: Option<String>,
+    pub user: Option<User>,
+    #[serde(default)]
+    pub http: HttpSettings,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NoSuchProfile(String),
+    Storage(ConfyError),
+    Token(io::Error),
+    Secret(SecretError),
+}
+
+/// What `profile list` shows about a profile, without its token.
+#[derive(Debug, Serialize, Clone)]
+pub struct ProfileSummary {
+    pub name: String,
+    pub current: bool,
+    pub endpoint: String,
+    pub default_org: Option<usize>,
+    pub default_pipe: Option<usize>,
+    pub user: Option<String>,
+}
+
+/// What `auth status` shows about the profile in use.
+#[derive(Debug, Serialize, Clone)]
+pub struct AuthStatus {
+    pub profile: String,
+    pub endpoint: String,
+    pub user: Option<String>,
+    pub user_id: Option<u32>,
+    pub validated_at: Option<String>,
+}
+
+impl ::std::default::Default for Config {
+    fn default() -> Self {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(DEFAULT_PROFILE.to_string(), Profile::default());
+        Self {
+            current_profile: DEFAULT_PROFILE.to_string(),
+            key_source: KeySource::default(),
+            secret_file: None,
+            profiles,
+        }
+    }
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> Result<&Profile, ConfigError> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ConfigError::NoSuchProfile(name.to_string()))
+    }
+
+    pub fn summaries(&self) -> Vec<ProfileSummary> {
+        self.profiles
+            .iter()
+            .map(|(name, profile)| ProfileSummary {
+                name: name.to_string(),
+                current: *name == self.current_profile,
+                endpoint: profile.endpoint().to_string(),
+                default_org: profile.default_org,
+                default_pipe: profile.default_pipe,
+                user: profile.user.as_ref().map(|user| user.info.name.to_string()),
+            })
+            .collect()
+    }
+
+    /// Opens the keyring the API keys are sealed with, first sealing and
+    /// storing any key still kept in plaintext by an older version. Until a
+    /// key is sealed a typed passphrase has to be repeated.
+    pub fn keyring(&mut self) -> Result<Keyring, ConfigError> {
+        let any_sealed = self
+            .profiles
+            .values()
+            .filter_map(|profile| profile.user.as_ref())
+            .any(|user| secret::is_sealed(&user.api_key));
+        let keyring = if any_sealed {
+            Keyring::open(&self.key_source, &self.secret_file())?
+        } else {
+            self.new_keyring()?
+        };
+        let mut migrated = false;
+        for profile in self.profiles.values_mut() {
+            if let Some(user) = profile.user.as_mut() {
+                if !secret::is_sealed(&user.api_key) {
+                    user.api_key = keyring.seal(&user.api_key)?;
+                    migrated = true;
+                }
+            }
+        }
+        if migrated {
+            store(self)?;
+        }
+        Ok(keyring)
+    }
+
+    /// A keyring for a key source that has nothing sealed with it yet.
+    pub fn new_keyring(&self) -> Result<Keyring, ConfigError> {
+        Ok(Keyring::create(&self.key_source, &self.secret_file())?)
+    }
+
+    fn secret_file(&self) -> PathBuf {
+        self.secret_file.clone().unwrap_or_else(|| {
+            dirs::data_local_dir()
+                .unwrap_or_default()
+                .join(APP_NAME)
+                .join(SECRET_FILE_NAME)
+        })
+    }
+}
+
+impl Profile {
+    pub fn endpoint(&self) -> &str {
+        self.endpoint
+            .as_ref()
+            .map_or(DEFAULT_ENDPOINT, |endpoint| endpoint)
+    }
+
+    /// Whether the stored key was checked against Pipefy recently enough
+    /// to skip checking it again.
+    pub fn recently_validated(&self) -> bool {
+        self.validated_at
+            .as_ref()
+            .and_then(|validated_at| DateTime::parse_from_rfc3339(validated_at).ok())
+            .map_or(false, |validated_at| {
+                Utc::now().signed_duration_since(validated_at)
+                    < Duration::hours(VALIDATION_TTL_HOURS)
+            })
+    }
+
+    pub fn mark_validated(&mut self) {
+        self.validated_at = Some(Utc::now().to_rfc3339());
+    }
+
+    /// The profile with its API key decrypted, ready to be used.
+    pub fn unlocked(&self, keyring: &Keyring) -> Result<Profile, ConfigError> {
+        let mut profile = self.clone();
+        if let Some(user) = profile.user.as_mut() {
+            if secret::is_sealed(&user.api_key) {
+                user.api_key = keyring.unseal(&user.api_key)?;
+            }
+        }
+        Ok(profile)
+    }
+
+    /// The profile with its API key encrypted, ready to be stored.
+    pub fn locked(&self, keyring: &Keyring) -> Result<Profile, ConfigError> {
+        let mut profile = self.clone();
+        if let Some(user) = profile.user.as_mut() {
+            if !secret::is_sealed(&user.api_key) {
+                user.api_key = keyring.seal(&user.api_key)?;
+            }
+        }
+        Ok(profile)
+    }
+
+    pub fn status(&self, name: &str) -> AuthStatus {
+        AuthStatus {
+            profile: name.to_string(),
+            endpoint: self.endpoint().to_string(),
+            user: self.user.as_ref().map(|user| user.info.name.to_string()),
+            user_id: self.user.as_ref().map(|user| user.info.id),
+            validated_at: self.validated_at.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ProfileSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let marker = if self.current { "* " } else { "  " };
+        write!(
+            f,
+            "{}{} ({}){}",
+            marker,
+            self.name,
+            self.endpoint,
+            self.user
+                .as_ref()
+                .map_or(String::new(), |user| format!(" as {}", user))
+        )
+    }
+}
+
+impl fmt::Display for AuthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.user, self.user_id) {
+            (Some(user), Some(user_id)) => write!(
+                f,
+                "Profile: {},\nendpoint: {},\nuser: {} ({}),\nvalidated at: {}",
+                self.profile,
+                self.endpoint,
+                user,
+                user_id,
+                self.validated_at.as_ref().map_or("never", |at| at)
+            ),
+            _ => write!(
+                f,
+                "Profile: {},\nendpoint: {},\nnot logged in",
+                self.profile, self.endpoint
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::NoSuchProfile(name) => write!(f, "There is no profile named {}", name),
+            ConfigError::Storage(e) => write!(f, "Could not read or write the config: {}", e),
+            ConfigError::Token(e) => write!(f, "Could not read the API key: {}", e),
+            ConfigError::Secret(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConfigError::Storage(e) => Some(e),
+            ConfigError::Token(e) => Some(e),
+            ConfigError::Secret(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Token(e)
+    }
+}
+
+impl From<SecretError> for ConfigError {
+    fn from(e: SecretError) -> ConfigError {
+        ConfigError::Secret(e)
+    }
+}
+
+impl From<ConfyError> for ConfigError {
+    fn from(e: ConfyError) -> ConfigError {
+        ConfigError::Storage(e)
+    }
+}
+
+/// Loads the config, turning a config from before profiles existed
+/// (a bare `User`) into the default profile. Its API key is sealed the
+/// first time the keyring is opened.
+pub fn load() -> Result<Config, ConfyError> {
+    match confy::load::<Config>(APP_NAME) {
+        Ok(config) => Ok(config),
+        Err(e) => match confy::load::<User>(APP_NAME) {
+            Ok(user) => {
+                let mut config = Config::default();
+                config.profiles.insert(
+                    DEFAULT_PROFILE.to_string(),
+                    Profile {
+                        user: Some(user),
+                        ..Profile::default()
+                    },
+                );
+                store(&config)?;
+                Ok(config)
+            }
+            Err(_) => Err(e),
+        },
+    }
+}
+
+pub fn store(config: &Config) -> Result<(), ConfyError> {
+    confy::store(APP_NAME, config)
+}
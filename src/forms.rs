@@ -0,0 +1,154 @@
+use crate::field::CardFieldValue;
+use crate::graphql::{FieldValue, FormField};
+use dialoguer::{theme::ColorfulTheme, Checkboxes, Input, Select};
+use serde_json::{json, Value};
+
+/// Walks a pipe's start form, asking for every field in order.
+pub fn fill_start_form(form_fields: &[FormField]) -> (Option<String>, Vec<FieldValue>) {
+    let title: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Title (leave empty to let Pipefy name it)")
+        .allow_empty(true)
+        .interact()
+        .unwrap();
+    let title = if title.is_empty() { None } else { Some(title) };
+
+    let values = form_fields
+        .iter()
+        .filter_map(|form_field| {
+            ask_field(form_field).map(|field_value| FieldValue {
+                field_id: form_field.id.to_string(),
+                field_value,
+            })
+        })
+        .collect();
+    (title, values)
+}
+
+fn ask_field(form_field: &FormField) -> Option<Value> {
+    let prompt = if form_field.required {
+        format!("{} *", form_field.label)
+    } else {
+        form_field.label.to_string()
+    };
+    match form_field.kind.as_str() {
+        // Fields without options are asked for as text below
+        "select" | "radio_vertical" | "radio_horizontal" if !form_field.options.is_empty() => {
+            let mut options = form_field.options.clone();
+            if !form_field.required {
+                options.push("(skip)".to_string());
+            }
+            let selected = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(&prompt)
+                .default(0)
+                .items(&options[..])
+                .interact()
+                .unwrap();
+            if selected == form_field.options.len() {
+                None
+            } else {
+                Some(json!(options[selected]))
+            }
+        }
+        "checklist_vertical" | "checklist_horizontal" if !form_field.options.is_empty() => loop {
+            let checked = Checkboxes::with_theme(&ColorfulTheme::default())
+                .with_prompt(&prompt)
+                .items(&form_field.options[..])
+                .interact()
+                .unwrap();
+            let values: Vec<&String> = checked.iter().map(|i| &form_field.options[*i]).collect();
+            if !values.is_empty() {
+                break Some(json!(values));
+            } else if !form_field.required {
+                break None;
+            }
+            println!(
+                "{} is required, check at least one option",
+                form_field.label
+            );
+        },
+        _ => loop {
+            let value: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt(&format!("{} ({})", prompt, hint(form_field)))
+                .allow_empty(!form_field.required)
+                .interact()
+                .unwrap();
+            match form_field.parse_value(&value) {
+                Ok(Value::Null) => break None,
+                Ok(value) => break Some(value),
+                Err(e) => println!("{}", e),
+            }
+        },
+    }
+}
+
+/// Asks for a new value for `form_field`, starting from its `current` value,
+/// until it passes validation.
+pub fn edit_field(form_field: &FormField, current: Option<&CardFieldValue>) -> Value {
+    match form_field.kind.as_str() {
+        // Fields without options are asked for as text below
+        "select" | "radio_vertical" | "radio_horizontal" if !form_field.options.is_empty() => {
+            let mut options = form_field.options.clone();
+            if !form_field.required {
+                options.push("(clear)".to_string());
+            }
+            // An empty optional field starts on (clear)
+            let selected_now = match current {
+                Some(CardFieldValue::Select(option)) => {
+                    form_field.options.iter().position(|o| o == option)
+                }
+                _ if !form_field.required => Some(form_field.options.len()),
+                _ => None,
+            };
+            let selected = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt(&form_field.label)
+                .default(selected_now.unwrap_or(0))
+                .items(&options[..])
+                .interact()
+                .unwrap();
+            if selected == form_field.options.len() {
+                Value::Null
+            } else {
+                json!(options[selected])
+            }
+        }
+        "checklist_vertical" | "checklist_horizontal" if !form_field.options.is_empty() => {
+            let prompt = match current {
+                Some(current) => format!("{} (now: {})", form_field.label, current),
+                None => form_field.label.to_string(),
+            };
+            let checked = Checkboxes::with_theme(&ColorfulTheme::default())
+                .with_prompt(&prompt)
+                .items(&form_field.options[..])
+                .interact()
+                .unwrap();
+            let values: Vec<&String> = checked.iter().map(|i| &form_field.options[*i]).collect();
+            json!(values)
+        }
+        _ => loop {
+            let theme = ColorfulTheme::default();
+            let mut input = Input::<String>::with_theme(&theme);
+            input
+                .with_prompt(&format!("{} ({})", form_field.label, hint(form_field)))
+                .allow_empty(!form_field.required);
+            if let Some(current) = current {
+                input.default(current.as_input());
+            }
+            let input = input.interact().unwrap();
+            match form_field.parse_value(&input) {
+                Ok(value) => break value,
+                Err(e) => println!("{}", e),
+            }
+        },
+    }
+}
+
+fn hint(form_field: &FormField) -> &str {
+    match form_field.kind.as_str() {
+        "number" | "currency" => "number",
+        "date" => "YYYY-MM-DD",
+        "datetime" | "due_date" => "YYYY-MM-DD HH:MM",
+        "assignee_select" => "user ids, comma separated",
+        "label_select" => "label ids, comma separated",
+        _ => "text",
+    }
+}
@@ -0,0 +1,8 @@
+//! Pipefy's GraphQL API from Rust: a client, the models its answers are
+//! decoded into and the errors it returns. Nothing in here reads from or
+//! writes to the terminal, requests are reported through the `log` crate.
+pub mod pipefy;
+
+pub use crate::client::{Endpoint, HttpSettings, PipefyClient};
+pub use crate::error::PipefyError;
+pub use crate::pipefy::{client, error, field, graphql, search, trace, user, watch, webhook};
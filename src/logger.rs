@@ -0,0 +1,32 @@
+use log::{Level, Log, Metadata, Record};
+
+/// Prints the library's log on stderr: warnings by default, requests and
+/// retries with -v, whole exchanges with -vv.
+struct StderrLogger {
+    level: Level,
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with("pipe_cli")
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("{}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+pub fn init(verbosity: u8) {
+    let level = match verbosity {
+        0 => Level::Warn,
+        1 => Level::Info,
+        _ => Level::Debug,
+    };
+    if log::set_boxed_logger(Box::new(StderrLogger { level })).is_ok() {
+        log::set_max_level(level.to_level_filter());
+    }
+}
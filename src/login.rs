@@ -0,0 +1,82 @@
+use crate::client::Endpoint;
+use crate::error::PipefyError;
+use crate::user::{self, User};
+use dialoguer::{theme::ColorfulTheme, PasswordInput};
+use std::io::{self, Read};
+use std::path::Path;
+use std::{env, fs, process};
+
+const TOKEN_VARIABLE: &str = "PIPEFY_TOKEN";
+const MAX_ATTEMPTS: usize = 3;
+
+pub fn test_existing_api_key(user: User, endpoint: &Endpoint) -> User {
+    match user::verify(user.api_key, endpoint) {
+        Ok(user) => user,
+        Err(PipefyError::Unauthorized) => {
+            println!("Your API key is invalid, please update it");
+            get_working_api_key(endpoint)
+        }
+        Err(e) => give_up(e),
+    }
+}
+pub fn get_working_api_key(endpoint: &Endpoint) -> User {
+    if !atty::is(atty::Stream::Stdin) {
+        eprintln!(
+            "No API key to use. Set {}, pass --token-file or --token-stdin, \
+             or run pipe_cli in a terminal to type it",
+            TOKEN_VARIABLE
+        );
+        process::exit(PipefyError::Unauthorized.exit_code())
+    }
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match user::verify(get_api_key(), endpoint) {
+            Ok(user) => return user,
+            Err(PipefyError::Unauthorized) if attempts < MAX_ATTEMPTS => {
+                println!("Invalid API key, please try again")
+            }
+            Err(e) => give_up(e),
+        }
+    }
+}
+
+/// Validates a token that came from outside the config, without prompting.
+pub fn user_from_token(api_key: String, endpoint: &Endpoint) -> User {
+    match user::verify(api_key, endpoint) {
+        Ok(user) => user,
+        Err(e) => give_up(e),
+    }
+}
+
+/// Looks for a token given outside of the stored profile: `PIPEFY_TOKEN`,
+/// then `token_file`, then stdin when `token_stdin` is set.
+pub fn external_token(token_file: Option<&Path>, token_stdin: bool) -> io::Result<Option<String>> {
+    if let Ok(token) = env::var(TOKEN_VARIABLE) {
+        if !token.trim().is_empty() {
+            return Ok(Some(token.trim().to_string()));
+        }
+    }
+    if let Some(token_file) = token_file {
+        return Ok(Some(fs::read_to_string(token_file)?.trim().to_string()));
+    }
+    if token_stdin {
+        let mut token = String::new();
+        io::stdin().read_to_string(&mut token)?;
+        return Ok(Some(token.trim().to_string()));
+    }
+    Ok(None)
+}
+
+fn give_up(e: PipefyError) -> ! {
+    eprintln!("{}", e);
+    process::exit(e.exit_code())
+}
+
+fn get_api_key() -> String {
+    let new_api_key = PasswordInput::with_theme(&ColorfulTheme::default())
+        .with_prompt("Your API key is not defined, please type it")
+        .interact()
+        .unwrap();
+    new_api_key.into()
+}
@@ -5,42 +5,95 @@ extern crate serde;
 extern crate serde_json;
 extern crate structopt;
 mod args;
-mod pipefy;
-use confy::{load, store};
+mod board;
+mod commands;
+mod config;
+mod forms;
+mod logger;
+mod login;
+mod picker;
+mod render;
+mod secret;
+mod serve;
+use args::Command;
+use client::{Endpoint, PipefyClient};
+use config::{Config, ConfigError, Profile};
 use dialoguer::{theme::ColorfulTheme, Input, Select};
+use error::PipefyError;
 use itertools::any;
-use pipefy::{graphql, user};
-use prettyprint::PrettyPrinter;
+use pipe_cli::{client, error, field, graphql, search, trace, user, watch, webhook};
 use quicli::prelude::*;
+use render::Output;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
-use user::User;
+use trace::{Replay, Tracer};
 
 fn main() -> CliResult {
-    welcome();
-    let stored_user = load::<User>("pipe_cli");
-    let user = match stored_user {
-        Ok(user) => user::test_existing_api_key(user),
-        _ => user::get_working_api_key(),
+    let args = args::Opts::from_args();
+    logger::init(args.verbose);
+    let mut config = config::load()?;
+    let profile_name = args
+        .profile
+        .clone()
+        .unwrap_or_else(|| config.current_profile.to_string());
+    let token = Token {
+        file: args.token_file.clone(),
+        stdin: args.token_stdin,
     };
+    if let Some(Command::Profile(profile_command)) = args.command {
+        exit_on_error(commands::profile(
+            &mut config,
+            args.output,
+            args.endpoint,
+            args.http.settings(),
+            profile_command,
+        ));
+        return Ok(());
+    }
+    let endpoint = connect(config.profile(&profile_name)?, &args);
+    match args.command {
+        Some(Command::Auth(auth_command)) => {
+            let external_token = token.read()?;
+            exit_on_error(commands::auth(
+                &mut config,
+                &profile_name,
+                &endpoint,
+                external_token,
+                args.output,
+                auth_command,
+            ));
+            return Ok(());
+        }
+        Some(command) => {
+            let profile = load_profile(&mut config, &profile_name, &endpoint, &token)?;
+            let user = profile.user.as_ref().unwrap();
+            let client = PipefyClient::new(&user.api_key, &endpoint);
+            exit_on_error(commands::run(&client, args.output, command));
+            return Ok(());
+        }
+        None => (),
+    }
+    welcome();
+    let profile = load_profile(&mut config, &profile_name, &endpoint, &token)?;
+    let user = profile.user.as_ref().unwrap();
     println!("Hello {}! Welcome to Pipefy CLI", user.info.name);
-    store("pipe_cli", &user)?;
-    let api_key = &user.api_key;
-    let args = args::Opts::from_args();
+    let client = PipefyClient::new(&user.api_key, &endpoint);
+    let output = args.output;
     let no_selection = !any(&[args.pipe_id, args.card_id], |id| id.is_some());
     loop {
         if no_selection {
-            let (selected_option, inputed_id) = main_select();
+            let (selected_option, inputed_id) = main_select(&profile);
             match selected_option {
-                0 => pipe_sub_select(&api_key, inputed_id),
-                1 => organization_sub_select(&api_key, inputed_id),
-                2 => card_sub_select(&api_key, inputed_id),
+                0 => pipe_sub_select(&client, output, inputed_id),
+                1 => organization_sub_select(&client, output, inputed_id),
+                2 => card_sub_select(&client, output, inputed_id),
                 _ => break,
             };
         } else {
             if args.pipe_id.is_some() {
-                pipe_sub_select(&api_key, args.pipe_id.unwrap());
+                pipe_sub_select(&client, output, args.pipe_id.unwrap());
             } else if args.card_id.is_some() {
-                card_sub_select(&api_key, args.card_id.unwrap());
+                card_sub_select(&client, output, args.card_id.unwrap());
                 break;
             }
         };
@@ -49,7 +102,117 @@ fn main() -> CliResult {
     Ok(())
 }
 
-fn main_select<'a>() -> (usize, usize) {
+/// Where the API key may come from besides the profile.
+struct Token {
+    file: Option<PathBuf>,
+    stdin: bool,
+}
+
+impl Token {
+    fn read(&self) -> Result<Option<String>, ConfigError> {
+        let token_file = self.file.as_ref().map(|path| path.as_path());
+        Ok(login::external_token(token_file, self.stdin)?)
+    }
+}
+
+/// Builds the HTTP client shared by the whole process, with the flags
+/// taking precedence over the profile.
+fn connect(profile: &Profile, args: &args::Opts) -> Endpoint {
+    let url = args
+        .endpoint
+        .as_ref()
+        .map_or(profile.endpoint(), |endpoint| endpoint);
+    let settings = profile.http.clone().or(args.http.settings());
+    let file_error = |what: &str, path: &PathBuf, e: std::io::Error| {
+        PipefyError::Transport(format!("{} {}: {}", what, path.display(), e))
+    };
+    let build = || -> Result<Endpoint, PipefyError> {
+        let mut endpoint = Endpoint::new(url, &settings)?;
+        // Loaded first, creating the trace file empties it
+        if let Some(path) = &args.replay {
+            let replay = Replay::load(path).map_err(|e| file_error("replay file", path, e))?;
+            endpoint = endpoint.with_replay(replay);
+        }
+        if let Some(path) = &args.trace_file {
+            if args
+                .replay
+                .as_ref()
+                .map_or(false, |replay| overwrites(path, replay))
+            {
+                return Err(PipefyError::Transport(format!(
+                    "The trace file {} would overwrite the recording being replayed",
+                    path.display()
+                )));
+            }
+            let tracer = Tracer::create(path).map_err(|e| file_error("trace file", path, e))?;
+            endpoint = endpoint.with_tracer(tracer);
+        }
+        Ok(endpoint)
+    };
+    build().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(e.exit_code())
+    })
+}
+
+/// Whether writing a trace to `trace` would replace the file `replay` is,
+/// or one of the files of the directory it is.
+fn overwrites(trace: &Path, replay: &Path) -> bool {
+    let replay = match replay.canonicalize() {
+        Ok(replay) => replay,
+        Err(_) => return false,
+    };
+    let dir = trace
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let trace = match (dir.canonicalize(), trace.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => return false,
+    };
+    trace == replay || trace.parent() == Some(replay.as_path())
+}
+
+/// Makes sure the profile has a working API key and stores it back sealed.
+/// Keys given through `token` are used as they are and never stored.
+fn load_profile(
+    config: &mut Config,
+    profile_name: &str,
+    endpoint: &Endpoint,
+    token: &Token,
+) -> Result<Profile, ConfigError> {
+    if let Some(api_key) = token.read()? {
+        let mut profile = config.profile(profile_name)?.clone();
+        profile.user = Some(login::user_from_token(api_key, endpoint));
+        return Ok(profile);
+    }
+    let keyring = config.keyring()?;
+    let mut profile = config.profile(profile_name)?.unlocked(&keyring)?;
+    let user = match profile.user.take() {
+        Some(user) if profile.recently_validated() => user,
+        Some(user) => login::test_existing_api_key(user, endpoint),
+        None => login::get_working_api_key(endpoint),
+    };
+    if !profile.recently_validated() {
+        profile.mark_validated();
+    }
+    profile.user = Some(user);
+    config
+        .profiles
+        .insert(profile_name.to_string(), profile.locked(&keyring)?);
+    config::store(config)?;
+    Ok(profile)
+}
+
+fn exit_on_error(result: Result<(), Box<std::error::Error>>) -> () {
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        let exit_code = e.downcast_ref::<PipefyError>().map_or(1, |e| e.exit_code());
+        std::process::exit(exit_code);
+    }
+}
+
+fn main_select<'a>(profile: &Profile) -> (usize, usize) {
     let selections = &["💈 Pipe", "🏭 Organization", "🃏 Card", "Exit"];
 
     let select = Select::with_theme(&ColorfulTheme::default())
@@ -62,24 +225,38 @@ fn main_select<'a>() -> (usize, usize) {
     if select == 3 {
         return (3, 0);
     }
-    let input = Input::with_theme(&ColorfulTheme::default())
-        .with_prompt("The ID, please")
-        .interact()
-        .unwrap();
+    let default_id = match select {
+        0 => profile.default_pipe,
+        1 => profile.default_org,
+        _ => None,
+    };
+    let theme = ColorfulTheme::default();
+    let mut input = Input::with_theme(&theme);
+    input.with_prompt("The ID, please");
+    if let Some(default_id) = default_id {
+        input.default(default_id);
+    }
 
-    (select, input)
+    (select, input.interact().unwrap())
 }
 
-fn pipe_sub_select<'a>(api_key: &str, pipe_id: usize) -> () {
-    if let Err(_) = graphql::pipe_name_query(api_key, pipe_id as i32) {
-        println!("Unauthorized");
-        return ();
-    }
+fn pipe_sub_select<'a>(client: &PipefyClient, output: Output, pipe_id: usize) -> () {
+    let pipe = match client.pipe(pipe_id) {
+        Ok(pipe) => pipe,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+    print_or_report(render::one(output, "Pipe Name", &pipe));
     let selections = &[
         "See All Phases",
         "See All Cards",
         "Select One Phase",
         "Select One Card",
+        "Search Cards",
+        "Create Card",
+        "Open Board",
     ];
 
     let select = Select::with_theme(&ColorfulTheme::default())
@@ -90,81 +267,194 @@ fn pipe_sub_select<'a>(api_key: &str, pipe_id: usize) -> () {
         .unwrap();
 
     match select {
-        0 => {
-            if let Err(_) = graphql::pipe_phases_query(api_key, pipe_id as i32) {
-                println!("Unauthorized");
-            }
-        }
+        0 => print_or_report(render::list(output, "Phases", &pipe.phases)),
         1 => {
-            if let Err(_) = graphql::pipe_cards_query(api_key, pipe_id as i32) {
-                println!("Unauthorized");
-            }
+            let pages = client.pipe_cards(pipe_id, graphql::DEFAULT_PAGE_SIZE, None);
+            print_or_report(render::stream(output, "Cards", pages));
         }
 
         2 => {
-            phases_pipe_selection(api_key, pipe_id);
+            phases_pipe_selection(client, output, &pipe.phases);
         }
 
         3 => {
-            cards_pipe_selection(api_key, pipe_id as i32);
+            cards_pipe_selection(client, output, pipe_id);
+        }
+        4 => {
+            search_cards_selection(client, output, pipe_id);
+        }
+        5 => {
+            create_card_selection(client, output, pipe_id);
         }
+        6 => print_or_report(board::run(client, pipe_id)),
         _ => {
             println!("Invalid option");
         }
     }
 }
 
-fn phases_pipe_selection(api_key: &str, pipe_id: usize) -> () {
-    //TODO
-    //Make it actually do something here 😅
-    let selections = &["See all", "Select one"];
+fn search_cards_selection(client: &PipefyClient, output: Output, pipe_id: usize) -> () {
+    let title = Input::<String>::with_theme(&ColorfulTheme::default())
+        .with_prompt("Title contains")
+        .interact()
+        .unwrap();
+    let filter = search::CardFilter {
+        title: Some(title),
+        ..search::CardFilter::default()
+    };
+    let pages = client.search_cards(pipe_id, &filter, graphql::DEFAULT_PAGE_SIZE, None);
+    print_or_report(render::stream(output, "Cards", pages));
+}
 
-    let select = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("What do you want to see?")
+fn phases_pipe_selection(client: &PipefyClient, output: Output, phases: &[graphql::Phase]) -> () {
+    if phases.is_empty() {
+        println!("This pipe has no phases");
+        return ();
+    }
+    let phase_select = match picker::pick("Which phase?", phases).unwrap() {
+        Some(phase_select) => phase_select,
+        None => return (),
+    };
+
+    let phase = match client.phase(phases[phase_select].id) {
+        Ok(phase) => phase,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+    print_or_report(render::one(output, "Phase", &phase));
+    let pages = client.phase_cards(phase.id, graphql::DEFAULT_PAGE_SIZE, None);
+    print_or_report(render::stream(output, "Cards", pages));
+}
+fn cards_pipe_selection(client: &PipefyClient, output: Output, pipe_id: usize) -> () {
+    let cards = match client.all_pipe_cards(pipe_id) {
+        Ok(cards) => cards,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+
+    let card_select = match picker::pick("Which card?", &cards).unwrap() {
+        Some(card_select) => card_select,
+        None => return (),
+    };
+
+    let card = cards.get(card_select).unwrap();
+    render::one(output, "Card", card).expect("Something went wrong printing the Card");
+
+    let actions = &["Move to another phase", "Edit a field", "Back"];
+    let action = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("What now?")
         .default(0)
-        .items(selections)
+        .items(actions)
         .interact()
         .unwrap();
+    match action {
+        0 => move_card_selection(client, output, card.id),
+        1 => edit_field_selection(client, output, card),
+        _ => (),
+    }
 }
-fn cards_pipe_selection(api_key: &str, pipe_id: i32) -> () {
-    let cards = graphql::pipe_cards_select(api_key, pipe_id).unwrap();
 
-    let card_selection: Vec<String> = cards
+fn edit_field_selection(client: &PipefyClient, output: Output, card: &graphql::Card) -> () {
+    let editable_fields = match client.editable_fields(card.id) {
+        Ok(editable_fields) => editable_fields,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+    if editable_fields.is_empty() {
+        println!("This card has no fields to edit");
+        return ();
+    }
+    let field_selection: Vec<String> = editable_fields
         .iter()
-        .map(|card_node| card_node.node.title.to_string())
+        .map(|form_field| match card.field(&form_field.id) {
+            Some(card_field) => card_field.to_string(),
+            None => format!("{}: ", form_field.label),
+        })
         .collect();
-
-    let card_select = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Which card?")
+    let field_select = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which field?")
         .default(0)
         .paged(true)
-        .items(&card_selection[..])
+        .items(&field_selection[..])
         .interact()
         .unwrap();
+    let form_field = &editable_fields[field_select];
+    let current = card
+        .field(&form_field.id)
+        .map(|card_field| &card_field.value);
+    let new_value = forms::edit_field(form_field, current);
+    match client.update_card_field(card.id, &form_field.id, new_value) {
+        Ok(card) => print_or_report(render::one(output, "Card", &card)),
+        Err(e) => println!("{}", e),
+    }
+}
 
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
+fn move_card_selection(client: &PipefyClient, output: Output, card_id: usize) -> () {
+    let moves = match client.move_targets(card_id) {
+        Ok(moves) => moves,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+    if moves.cards_can_be_moved_to_phases.is_empty() {
+        println!("Cards in {} can not be moved anywhere", moves.name);
+        return ();
+    }
+    let destinations: Vec<String> = moves
+        .cards_can_be_moved_to_phases
+        .iter()
+        .map(|phase| phase.name.to_string())
+        .collect();
+    let destination = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(&format!("Move from {} to", moves.name))
+        .default(0)
+        .items(&destinations[..])
+        .interact()
         .unwrap();
-
-    let card_node = cards.get(card_select).unwrap();
-    print
-        .string_with_header(card_node.node.to_string(), "Card".to_string())
-        .expect("Something went wrong printing the Card");
+    let phase_id = moves.cards_can_be_moved_to_phases[destination].id;
+    match client.move_card(card_id, phase_id) {
+        Ok(card) => print_or_report(render::one(output, "Card", &card)),
+        Err(e) => println!("{}", e),
+    }
 }
 
-fn card_sub_select(api_key: &str, id: usize) -> () {
-    if let Err(_) = graphql::card_query_and_print(api_key, id as i32) {
-        println!("Unauthorized");
+fn create_card_selection(client: &PipefyClient, output: Output, pipe_id: usize) -> () {
+    let form_fields = match client.start_form_fields(pipe_id) {
+        Ok(form_fields) => form_fields,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+    let (title, values) = forms::fill_start_form(&form_fields);
+    match client.create_card(pipe_id, title.as_ref().map(|t| t.as_str()), &values) {
+        Ok(card) => print_or_report(render::one(output, "Card", &card)),
+        Err(e) => println!("{}", e),
     }
 }
-fn organization_sub_select<'a>(api_key: &str, company_id: usize) -> () {
-    if let Err(_) = graphql::organization_name_query(api_key, company_id as i32) {
-        println!("Unauthorized");
-        return ();
+
+fn card_sub_select(client: &PipefyClient, output: Output, id: usize) -> () {
+    match client.card(id) {
+        Ok(card) => print_or_report(render::one(output, "Card", &card)),
+        Err(e) => println!("{}", e),
     }
+}
+fn organization_sub_select<'a>(client: &PipefyClient, output: Output, company_id: usize) -> () {
+    let organization = match client.organization(company_id) {
+        Ok(organization) => organization,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+    print_or_report(render::one(output, "Organization Name", &organization));
     let selections = &["💈💈 Pipes 💈💈", "👥👥 Members 👥👥"];
 
     let select = Select::with_theme(&ColorfulTheme::default())
@@ -174,14 +464,12 @@ fn organization_sub_select<'a>(api_key: &str, company_id: usize) -> () {
         .interact()
         .unwrap();
     match select {
-        0 => {
-            if let Err(_) = graphql::org_pipes_query(api_key, company_id as i32) {
-                println!("Unauthorized");
-            }
-        }
+        0 => org_pipes_selection(client, output, company_id),
         1 => {
-            if let Err(_) = graphql::org_members_query(api_key, company_id as i32) {
-                println!("Unauthorized");
+            let members = &organization.members;
+            print_or_report(render::list(output, "Members", members));
+            if let Some(member_select) = picker::pick("Which member?", members).unwrap() {
+                print_or_report(render::one(output, "Member", &members[member_select]));
             }
         }
         _ => {
@@ -190,6 +478,27 @@ fn organization_sub_select<'a>(api_key: &str, company_id: usize) -> () {
     }
 }
 
+fn org_pipes_selection(client: &PipefyClient, output: Output, company_id: usize) -> () {
+    let pipes = match client.organization_pipes(company_id) {
+        Ok(pipes) => pipes,
+        Err(e) => {
+            println!("{}", e);
+            return ();
+        }
+    };
+    print_or_report(render::list(output, "Pipes", &pipes));
+
+    if let Some(pipe_select) = picker::pick("Open a pipe?", &pipes).unwrap() {
+        pipe_sub_select(client, output, pipes[pipe_select].id);
+    }
+}
+
+fn print_or_report(rendered: Result<(), Box<std::error::Error>>) -> () {
+    if let Err(e) = rendered {
+        println!("{}", e);
+    }
+}
+
 fn welcome() -> () {
     println!("
                                                                       `-/+o
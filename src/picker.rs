@@ -0,0 +1,272 @@
+use crate::field::Named;
+use crate::graphql::{Card, Member, OrgPipe, Phase};
+use console::{style, Key, Term};
+use std::io;
+
+const LIST_HEIGHT: usize = 10;
+const PREVIEW_HEIGHT: usize = 8;
+
+/// Something the picker can offer.
+pub trait Pickable {
+    /// The line shown in the list.
+    fn label(&self) -> String;
+
+    /// What typing is matched against, the label unless there is more to search.
+    fn haystack(&self) -> String {
+        self.label()
+    }
+
+    /// Details shown under the list while the item is highlighted.
+    fn preview(&self) -> String;
+}
+
+/// Lets the user narrow `items` down by typing and pick one with the arrows
+/// and Enter. Escape gives up and returns `None`.
+pub fn pick<T: Pickable>(prompt: &str, items: &[T]) -> io::Result<Option<usize>> {
+    let term = Term::stdout();
+    let width = term.size().1 as usize;
+    let haystacks: Vec<String> = items.iter().map(|item| item.haystack()).collect();
+    let mut query = String::new();
+    let mut selected = 0;
+    let mut drawn = 0;
+    loop {
+        let matches = rank(&haystacks, &query);
+        selected = selected.min(matches.len().saturating_sub(1));
+        let first = (selected + 1).saturating_sub(LIST_HEIGHT);
+        let mut lines = vec![format!("{} {}", style(prompt).bold(), query)];
+        for (row, index) in matches.iter().enumerate().skip(first).take(LIST_HEIGHT) {
+            let label = truncate(&items[*index].label(), width.saturating_sub(2));
+            if row == selected {
+                lines.push(format!("{} {}", style(">").cyan(), style(label).reverse()));
+            } else {
+                lines.push(format!("  {}", label));
+            }
+        }
+        if matches.is_empty() {
+            lines.push(format!("  {}", style("No match").dim()));
+        }
+        lines.push(style("─".repeat(width.min(60))).dim().to_string());
+        if let Some(index) = matches.get(selected) {
+            for line in items[*index].preview().lines().take(PREVIEW_HEIGHT) {
+                lines.push(truncate(line, width));
+            }
+        }
+        term.clear_last_lines(drawn)?;
+        for line in &lines {
+            term.write_line(line)?;
+        }
+        drawn = lines.len();
+        match term.read_key()? {
+            Key::ArrowUp => selected = selected.saturating_sub(1),
+            Key::ArrowDown if selected + 1 < matches.len() => selected += 1,
+            Key::Enter if !matches.is_empty() => {
+                term.clear_last_lines(drawn)?;
+                return Ok(matches.get(selected).cloned());
+            }
+            Key::Escape => {
+                term.clear_last_lines(drawn)?;
+                return Ok(None);
+            }
+            Key::Backspace => {
+                query.pop();
+                selected = 0;
+            }
+            Key::Char(typed) if !typed.is_control() => {
+                query.push(typed);
+                selected = 0;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Indexes of the haystacks that match `query`, best first. Ties keep
+/// their original order.
+fn rank(haystacks: &[String], query: &str) -> Vec<usize> {
+    let mut scored: Vec<(usize, i64)> = haystacks
+        .iter()
+        .enumerate()
+        .filter_map(|(index, haystack)| score(haystack, query).map(|score| (index, score)))
+        .collect();
+    scored.sort_by(|a, b| b.1.cmp(&a.1));
+    scored.into_iter().map(|(index, _)| index).collect()
+}
+
+/// Scores a haystack holding every character of the query in order.
+/// Runs of consecutive characters and word starts score higher, gaps lower.
+pub fn score(haystack: &str, query: &str) -> Option<i64> {
+    let haystack: Vec<char> = haystack.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = (position..haystack.len()).find(|&i| haystack[i] == wanted)?;
+        score += 1;
+        if previous.map_or(false, |previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !haystack[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= ((found - position) as i64).min(5);
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    if line.chars().count() <= width {
+        return line.to_string();
+    }
+    let mut truncated: String = line.chars().take(width.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+impl Pickable for Card {
+    fn label(&self) -> String {
+        let phase = self
+            .current_phase
+            .as_ref()
+            .map_or(String::new(), |phase| format!(" [{}]", phase.name));
+        format!("#{} {}{}", self.id, self.title, phase)
+    }
+
+    fn haystack(&self) -> String {
+        let values: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| field.value.to_string())
+            .collect();
+        format!("{} {} {}", self.id, self.title, values.join(" "))
+    }
+
+    fn preview(&self) -> String {
+        let names = |named: &[Named]| {
+            named
+                .iter()
+                .map(|named| named.name.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let mut lines = vec![
+            format!(
+                "Phase: {}",
+                self.current_phase
+                    .as_ref()
+                    .map_or("", |phase| phase.name.as_str())
+            ),
+            format!("Assignees: {}", names(&self.assignees)),
+            format!(
+                "Due: {}",
+                self.due_date.map_or(String::new(), |due| due
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string())
+            ),
+        ];
+        if !self.labels.is_empty() {
+            lines.push(format!("Labels: {}", names(&self.labels)));
+        }
+        lines.extend(
+            self.fields
+                .iter()
+                .filter(|field| !field.value.to_string().is_empty())
+                .map(|field| field.to_string()),
+        );
+        lines.join("\n")
+    }
+}
+
+impl Pickable for Phase {
+    fn label(&self) -> String {
+        format!("{} ({} cards)", self.name, self.cards_count)
+    }
+
+    fn preview(&self) -> String {
+        format!(
+            "id: {}\ndone: {}\n{}",
+            self.id,
+            self.done,
+            self.description
+                .as_ref()
+                .map_or("", |description| description)
+        )
+    }
+}
+
+impl Pickable for OrgPipe {
+    fn label(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn haystack(&self) -> String {
+        format!("{} {}", self.id, self.name)
+    }
+
+    fn preview(&self) -> String {
+        format!(
+            "id: {}\nphases: {}\ncards: {}\npublic: {}\nmember: {}",
+            self.id, self.phases_count, self.cards_count, self.public, self.member
+        )
+    }
+}
+
+impl Pickable for Member {
+    fn label(&self) -> String {
+        self.user.name.to_string()
+    }
+
+    fn haystack(&self) -> String {
+        format!("{} {} {}", self.user.id, self.user.name, self.role_name)
+    }
+
+    fn preview(&self) -> String {
+        format!("id: {}\nrole: {}", self.user.id, self.role_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_needs_every_character_in_order() {
+        assert!(score("Sales pipeline", "spl").is_some());
+        assert!(score("Sales pipeline", "lps").is_none());
+        assert_eq!(score("anything", ""), Some(0));
+    }
+
+    #[test]
+    fn score_ignores_case_and_spaces() {
+        assert_eq!(
+            score("ACME Renewal", "acme re"),
+            score("acme renewal", "ACMERE")
+        );
+    }
+
+    #[test]
+    fn score_prefers_runs_and_word_starts() {
+        assert!(score("Backlog", "back") > score("Big black cat", "back"));
+        assert!(score("the Won phase", "won") > score("known", "won"));
+    }
+
+    #[test]
+    fn rank_puts_the_best_first_and_drops_misses() {
+        let haystacks: Vec<String> = vec!["Globex upsell", "Black box", "Backlog", "Won"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(rank(&haystacks, "back"), vec![2, 1]);
+        assert_eq!(rank(&haystacks, "zzz"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn rank_keeps_ties_in_order() {
+        let haystacks: Vec<String> = vec!["card", "card", "card"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(rank(&haystacks, "ca"), vec![0, 1, 2]);
+    }
+}
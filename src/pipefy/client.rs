@@ -0,0 +1,598 @@
+use crate::error::{GraphQLError, PipefyError};
+use crate::graphql::{
+    Card, CardPayload, Connection, EditableFields, FieldValue, FormField, MoveTargets, OrgPipe,
+    OrgPipes, Organization, Person, Phase, PhaseMoves, Pipe, StartForm, CARD_QUERY,
+    CREATE_CARD_MUTATION, DEFAULT_PAGE_SIZE, EDITABLE_FIELDS_QUERY, ME_QUERY, MOVE_CARD_MUTATION,
+    MOVE_TARGETS_QUERY, ORGANIZATION_PIPES_QUERY, ORGANIZATION_QUERY, PHASE_CARDS_QUERY,
+    PHASE_QUERY, PIPE_CARDS_QUERY, PIPE_QUERY, SEARCH_CARDS_QUERY, START_FORM_QUERY,
+    UPDATE_CARD_FIELD_MUTATION,
+};
+use crate::search::{CardFilter, SearchPages};
+use crate::trace::{Exchange, Replay, Tracer, REDACTED_AUTHORIZATION};
+use crate::user::UserInfo;
+use chrono::{DateTime, Utc};
+use log::{debug, info, log_enabled, warn, Level};
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER, USER_AGENT};
+use reqwest::{Certificate, Proxy, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_ENDPOINT: &str = "https://app.pipefy.com/queries";
+const DEFAULT_USER_AGENT: &str = concat!("pipe_cli/", env!("CARGO_PKG_VERSION"));
+const DEFAULT_RETRIES: u32 = 3;
+const BASE_DELAY_MS: u64 = 500;
+const MAX_DELAY_MS: u64 = 30_000;
+const DEFAULT_MAX_WAIT_SECS: u64 = 120;
+
+#[derive(Serialize, Debug)]
+struct GraphQLRequest<'a> {
+    query: &'a str,
+    variables: Value,
+}
+
+/// How requests leave the machine. Unset values keep reqwest's defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HttpSettings {
+    pub proxy: Option<String>,
+    /// PEM certificate to trust on top of the system ones.
+    pub ca_bundle: Option<PathBuf>,
+    /// Seconds to wait for a response.
+    pub timeout: Option<u64>,
+    pub user_agent: Option<String>,
+    /// How many times a failed request may be sent again.
+    pub retries: Option<u32>,
+    /// Longest Retry-After in seconds worth waiting for before giving up.
+    pub max_wait: Option<u64>,
+}
+
+/// A GraphQL endpoint and the pooled HTTP client that reaches it. Clones
+/// share the same connections.
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    http: reqwest::Client,
+    url: String,
+    retries: u32,
+    max_wait: Duration,
+    tracer: Option<Tracer>,
+    replay: Option<Arc<Replay>>,
+}
+
+/// The parts of a response the client looks at.
+struct Reply {
+    status: StatusCode,
+    retry_after: Option<Duration>,
+    text: String,
+}
+
+/// A failed attempt, and whether sending the same request again may work.
+struct Failure {
+    error: PipefyError,
+    retry: bool,
+    retry_after: Option<Duration>,
+}
+
+/// Talks to Pipefy's GraphQL API on behalf of a single token.
+pub struct PipefyClient {
+    endpoint: Endpoint,
+    token: String,
+}
+
+impl HttpSettings {
+    /// These settings with the ones set in `overrides` taking precedence.
+    pub fn or(self, overrides: HttpSettings) -> HttpSettings {
+        HttpSettings {
+            proxy: overrides.proxy.or(self.proxy),
+            ca_bundle: overrides.ca_bundle.or(self.ca_bundle),
+            timeout: overrides.timeout.or(self.timeout),
+            user_agent: overrides.user_agent.or(self.user_agent),
+            retries: overrides.retries.or(self.retries),
+            max_wait: overrides.max_wait.or(self.max_wait),
+        }
+    }
+
+    fn client(&self) -> Result<reqwest::Client, PipefyError> {
+        let user_agent = self
+            .user_agent
+            .as_ref()
+            .map_or(DEFAULT_USER_AGENT, |user_agent| user_agent);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(user_agent).map_err(|_| {
+                PipefyError::Transport(format!("invalid User-Agent {}", user_agent))
+            })?,
+        );
+        let mut builder = reqwest::Client::builder().default_headers(headers);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(
+                Proxy::all(proxy.as_str())
+                    .map_err(|e| PipefyError::Transport(format!("proxy {}: {}", proxy, e)))?,
+            );
+        }
+        if let Some(ca_bundle) = &self.ca_bundle {
+            let certificate = fs::read(ca_bundle)
+                .map_err(|e| e.to_string())
+                .and_then(|pem| Certificate::from_pem(&pem).map_err(|e| e.to_string()))
+                .map_err(|e| {
+                    PipefyError::Transport(format!("CA bundle {}: {}", ca_bundle.display(), e))
+                })?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
+        builder
+            .build()
+            .map_err(|e| PipefyError::Transport(e.to_string()))
+    }
+}
+
+impl Endpoint {
+    pub fn new(url: &str, settings: &HttpSettings) -> Result<Endpoint, PipefyError> {
+        Ok(Endpoint {
+            http: settings.client()?,
+            url: url.to_string(),
+            retries: settings.retries.unwrap_or(DEFAULT_RETRIES),
+            max_wait: Duration::from_secs(settings.max_wait.unwrap_or(DEFAULT_MAX_WAIT_SECS)),
+            tracer: None,
+            replay: None,
+        })
+    }
+
+    /// Answers from `replay` instead of sending anything.
+    pub fn with_replay(mut self, replay: Replay) -> Endpoint {
+        self.replay = Some(Arc::new(replay));
+        self
+    }
+
+    /// Records every exchange with `tracer`.
+    pub fn with_tracer(mut self, tracer: Tracer) -> Endpoint {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    fn trace(&self, body: &GraphQLRequest, reply: &Result<Reply, Failure>, elapsed: Duration) {
+        if !log_enabled!(Level::Info) && self.tracer.is_none() {
+            return;
+        }
+        let exchange = Exchange {
+            time: Utc::now().to_rfc3339(),
+            url: self.url.to_string(),
+            authorization: REDACTED_AUTHORIZATION.to_string(),
+            query: body.query.to_string(),
+            variables: body.variables.clone(),
+            status: reply.as_ref().ok().map(|reply| reply.status.as_u16()),
+            duration_ms: elapsed.as_millis() as u64,
+            response: reply.as_ref().ok().map(|reply| {
+                serde_json::from_str(&reply.text)
+                    .unwrap_or_else(|_| Value::String(reply.text.clone()))
+            }),
+            error: reply
+                .as_ref()
+                .err()
+                .map(|failure| failure.error.to_string()),
+        };
+        if log_enabled!(Level::Debug) {
+            debug!(
+                "{}",
+                serde_json::to_string_pretty(&exchange).unwrap_or_default()
+            );
+        } else {
+            info!("{}", exchange.summary());
+        }
+        if let Some(tracer) = &self.tracer {
+            if let Err(e) = tracer.record(&exchange) {
+                warn!("Could not write the trace file: {}", e);
+            }
+        }
+    }
+}
+
+impl PipefyClient {
+    pub fn new(token: &str, endpoint: &Endpoint) -> PipefyClient {
+        PipefyClient {
+            endpoint: endpoint.clone(),
+            token: token.to_string(),
+        }
+    }
+
+    pub fn me(&self) -> Result<UserInfo, PipefyError> {
+        let me: Person = self.fetch(ME_QUERY, json!({}), "me")?;
+        Ok(UserInfo {
+            name: me.name,
+            id: me.id,
+        })
+    }
+
+    pub fn pipe(&self, id: usize) -> Result<Pipe, PipefyError> {
+        self.fetch(PIPE_QUERY, json!({ "id": id }), "pipe")
+    }
+
+    pub fn phase(&self, id: usize) -> Result<Phase, PipefyError> {
+        self.fetch(PHASE_QUERY, json!({ "id": id }), "phase")
+    }
+
+    pub fn phase_cards(
+        &self,
+        phase_id: usize,
+        page_size: usize,
+        limit: Option<usize>,
+    ) -> Connection<'_, Card> {
+        Connection::new(
+            self,
+            PHASE_CARDS_QUERY,
+            json!({ "id": phase_id }),
+            &["phase", "cards"],
+            page_size,
+            limit,
+        )
+    }
+
+    pub fn start_form_fields(&self, pipe_id: usize) -> Result<Vec<FormField>, PipefyError> {
+        let start_form: StartForm =
+            self.fetch(START_FORM_QUERY, json!({ "id": pipe_id }), "pipe")?;
+        Ok(start_form.start_form_fields)
+    }
+
+    pub fn create_card(
+        &self,
+        pipe_id: usize,
+        title: Option<&str>,
+        fields: &[FieldValue],
+    ) -> Result<Card, PipefyError> {
+        let input = json!({
+            "pipe_id": pipe_id,
+            "title": title,
+            "fields_attributes": fields,
+        });
+        let payload: CardPayload = self.fetch(
+            CREATE_CARD_MUTATION,
+            json!({ "input": input }),
+            "createCard",
+        )?;
+        Ok(payload.card)
+    }
+
+    pub fn card(&self, id: usize) -> Result<Card, PipefyError> {
+        self.fetch(CARD_QUERY, json!({ "id": id }), "card")
+    }
+
+    /// The start form fields plus the fields of the phase the card is in.
+    pub fn editable_fields(&self, card_id: usize) -> Result<Vec<FormField>, PipefyError> {
+        let editable: EditableFields =
+            self.fetch(EDITABLE_FIELDS_QUERY, json!({ "id": card_id }), "card")?;
+        let mut fields = editable.pipe.start_form_fields;
+        fields.extend(editable.current_phase.fields);
+        Ok(fields)
+    }
+
+    pub fn update_card_field(
+        &self,
+        card_id: usize,
+        field_id: &str,
+        value: Value,
+    ) -> Result<Card, PipefyError> {
+        let input = json!({
+            "card_id": card_id,
+            "field_id": field_id,
+            "new_value": value,
+        });
+        let payload: CardPayload = self.fetch(
+            UPDATE_CARD_FIELD_MUTATION,
+            json!({ "input": input }),
+            "updateCardField",
+        )?;
+        Ok(payload.card)
+    }
+
+    pub fn move_targets(&self, card_id: usize) -> Result<PhaseMoves, PipefyError> {
+        let targets: MoveTargets =
+            self.fetch(MOVE_TARGETS_QUERY, json!({ "id": card_id }), "card")?;
+        Ok(targets.current_phase)
+    }
+
+    pub fn move_card(&self, card_id: usize, phase_id: usize) -> Result<Card, PipefyError> {
+        let input = json!({
+            "card_id": card_id,
+            "destination_phase_id": phase_id,
+        });
+        let payload: CardPayload = self.fetch(
+            MOVE_CARD_MUTATION,
+            json!({ "input": input }),
+            "moveCardToPhase",
+        )?;
+        Ok(payload.card)
+    }
+
+    pub fn organization(&self, id: usize) -> Result<Organization, PipefyError> {
+        self.fetch(ORGANIZATION_QUERY, json!({ "id": id }), "organization")
+    }
+
+    /// Lists an organization's pipes, flagging the ones the current user is a member of.
+    pub fn organization_pipes(&self, org_id: usize) -> Result<Vec<OrgPipe>, PipefyError> {
+        let data = self.request(ORGANIZATION_PIPES_QUERY, json!({ "id": org_id }))?;
+        if data["organization"].is_null() {
+            return Err(PipefyError::NotFound(format!("organization {}", org_id)));
+        }
+        let me: Person = decode(&data["me"])?;
+        let organization: OrgPipes = decode(&data["organization"])?;
+        Ok(organization
+            .pipes
+            .into_iter()
+            .map(|mut pipe| {
+                pipe.phases_count = pipe.phases.len();
+                pipe.member = pipe.members.iter().any(|member| member.user.id == me.id);
+                pipe
+            })
+            .collect())
+    }
+
+    pub fn pipe_cards(
+        &self,
+        pipe_id: usize,
+        page_size: usize,
+        limit: Option<usize>,
+    ) -> Connection<'_, Card> {
+        Connection::new(
+            self,
+            PIPE_CARDS_QUERY,
+            json!({ "pipeId": pipe_id }),
+            &["allCards"],
+            page_size,
+            limit,
+        )
+    }
+
+    /// Cards of a pipe that pass `filter`. `limit` counts matching cards,
+    /// not the ones fetched to find them.
+    pub fn search_cards<'a>(
+        &'a self,
+        pipe_id: usize,
+        filter: &'a CardFilter,
+        page_size: usize,
+        limit: Option<usize>,
+    ) -> SearchPages<'a> {
+        let pages = Connection::new(
+            self,
+            SEARCH_CARDS_QUERY,
+            json!({ "pipeId": pipe_id, "search": filter.search() }),
+            &["cards"],
+            page_size,
+            None,
+        );
+        SearchPages::new(pages, filter, limit)
+    }
+
+    pub fn all_pipe_cards(&self, pipe_id: usize) -> Result<Vec<Card>, PipefyError> {
+        let mut cards = Vec::new();
+        for page in self.pipe_cards(pipe_id, DEFAULT_PAGE_SIZE, None) {
+            cards.extend(page?);
+        }
+        Ok(cards)
+    }
+
+    /// Sends one operation and hands back its `data` object. Failures that
+    /// may go away are retried with backoff, but mutations only when Pipefy
+    /// surely did not run them.
+    pub fn request(&self, query: &str, variables: Value) -> Result<Value, PipefyError> {
+        let body = GraphQLRequest { query, variables };
+        let mutation = query.trim_start().starts_with("mutation");
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let failure = match self.attempt(&body, mutation) {
+                Ok(data) => return Ok(data),
+                Err(failure) => failure,
+            };
+            if !failure.retry || attempt > self.endpoint.retries {
+                return Err(failure.error);
+            }
+            if let Some(wait) = failure.retry_after {
+                if wait > self.endpoint.max_wait {
+                    info!(
+                        "Attempt {} failed: {}, not waiting the {}s asked for",
+                        attempt,
+                        failure.error,
+                        wait.as_secs()
+                    );
+                    return Err(failure.error);
+                }
+            }
+            let delay = failure.retry_after.unwrap_or_else(|| backoff(attempt));
+            info!(
+                "Attempt {} failed: {}, retrying in {}ms",
+                attempt,
+                failure.error,
+                delay.as_millis()
+            );
+            thread::sleep(delay);
+        }
+    }
+
+    fn attempt(&self, body: &GraphQLRequest, mutation: bool) -> Result<Value, Failure> {
+        let started = Instant::now();
+        let reply = self.send(body, mutation);
+        self.endpoint.trace(body, &reply, started.elapsed());
+        let reply = reply?;
+        match reply.status {
+            StatusCode::UNAUTHORIZED => return Err(PipefyError::Unauthorized.into()),
+            StatusCode::FORBIDDEN => {
+                return Err(PipefyError::PermissionDenied(
+                    "the API key can not run this query".to_string(),
+                )
+                .into())
+            }
+            status @ StatusCode::TOO_MANY_REQUESTS | status @ StatusCode::SERVICE_UNAVAILABLE => {
+                return Err(Failure::new(
+                    PipefyError::HttpStatus(status.as_u16()),
+                    true,
+                    reply.retry_after,
+                ));
+            }
+            status @ StatusCode::BAD_GATEWAY | status @ StatusCode::GATEWAY_TIMEOUT => {
+                return Err(Failure::new(
+                    PipefyError::HttpStatus(status.as_u16()),
+                    !mutation,
+                    None,
+                ))
+            }
+            status if !status.is_success() => {
+                return Err(PipefyError::HttpStatus(status.as_u16()).into())
+            }
+            _ => (),
+        }
+        let response_body: Value = serde_json::from_str(&reply.text).map_err(PipefyError::from)?;
+        let errors: Vec<GraphQLError> = match response_body.get("errors") {
+            Some(errors) => decode(errors)?,
+            None => Vec::new(),
+        };
+        if !errors.is_empty() {
+            return Err(PipefyError::from_graphql(errors).into());
+        }
+        Ok(response_body["data"].to_owned())
+    }
+
+    fn send(&self, body: &GraphQLRequest, mutation: bool) -> Result<Reply, Failure> {
+        if let Some(replay) = &self.endpoint.replay {
+            return replay
+                .reply(body.query, &body.variables)
+                .map(Reply::recorded)
+                .ok_or_else(|| {
+                    let what = format!("{} with {}", body.query.trim(), body.variables);
+                    PipefyError::Unrecorded(what).into()
+                });
+        }
+        let network_failure = |e: reqwest::Error| {
+            let retry = !mutation && !e.is_serialization() && !e.is_redirect();
+            Failure::new(e.into(), retry, None)
+        };
+        let mut response = self
+            .endpoint
+            .http
+            .post(&self.endpoint.url)
+            .json(body)
+            .bearer_auth(&self.token)
+            .send()
+            .map_err(network_failure)?;
+        Ok(Reply {
+            status: response.status(),
+            retry_after: retry_after(&response),
+            text: response.text().map_err(network_failure)?,
+        })
+    }
+
+    fn fetch<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: Value,
+        field: &str,
+    ) -> Result<T, PipefyError> {
+        let wanted = match variables.get("id") {
+            Some(id) => format!("{} {}", field, id),
+            None => field.to_string(),
+        };
+        let data = self.request(query, variables)?;
+        if data[field].is_null() {
+            return Err(PipefyError::NotFound(wanted));
+        }
+        decode(&data[field])
+    }
+}
+
+impl Reply {
+    fn recorded(exchange: Exchange) -> Reply {
+        let text = match exchange.response {
+            Some(Value::String(text)) => text,
+            Some(response) => response.to_string(),
+            None => String::new(),
+        };
+        Reply {
+            status: exchange
+                .status
+                .and_then(|status| StatusCode::from_u16(status).ok())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            retry_after: None,
+            text,
+        }
+    }
+}
+
+impl Failure {
+    fn new(error: PipefyError, retry: bool, retry_after: Option<Duration>) -> Failure {
+        Failure {
+            error,
+            retry,
+            retry_after,
+        }
+    }
+}
+
+impl From<PipefyError> for Failure {
+    fn from(error: PipefyError) -> Failure {
+        Failure::new(error, false, None)
+    }
+}
+
+/// Exponential backoff with jitter, so parallel runs don't retry in step.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY_MS
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(MAX_DELAY_MS);
+    Duration::from_millis(rand::thread_rng().gen_range(delay / 2, delay + 1))
+}
+
+/// The wait asked for by a Retry-After header, in seconds or as a date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    date.signed_duration_since(Utc::now()).to_std().ok()
+}
+
+pub fn decode<T: DeserializeOwned>(value: &Value) -> Result<T, PipefyError> {
+    Ok(serde_json::from_value::<T>(value.to_owned())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_take_precedence_over_settings() {
+        let profile = HttpSettings {
+            proxy: Some("http://profile:3128".to_string()),
+            timeout: Some(30),
+            retries: Some(5),
+            max_wait: Some(60),
+            ..HttpSettings::default()
+        };
+        let flags = HttpSettings {
+            proxy: Some("http://flag:3128".to_string()),
+            max_wait: Some(300),
+            user_agent: Some("scripts/1.0".to_string()),
+            ..HttpSettings::default()
+        };
+        let settings = profile.or(flags);
+        assert_eq!(settings.proxy, Some("http://flag:3128".to_string()));
+        assert_eq!(settings.timeout, Some(30));
+        assert_eq!(settings.retries, Some(5));
+        assert_eq!(settings.max_wait, Some(300));
+        assert_eq!(settings.user_agent, Some("scripts/1.0".to_string()));
+        assert_eq!(settings.ca_bundle, None);
+    }
+
+    #[test]
+    fn backoff_stays_below_the_maximum() {
+        assert!(backoff(1) <= Duration::from_millis(BASE_DELAY_MS));
+        assert!(backoff(40) <= Duration::from_millis(MAX_DELAY_MS));
+    }
+}
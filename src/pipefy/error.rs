@@ -0,0 +1,127 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::{error, fmt};
+
+/// One entry of the `errors` array of a GraphQL response.
+#[derive(Deserialize, Debug, Clone)]
+pub struct GraphQLError {
+    pub message: String,
+    #[serde(default)]
+    pub path: Vec<Value>,
+}
+
+#[derive(Debug)]
+pub enum PipefyError {
+    Network(reqwest::Error),
+    HttpStatus(u16),
+    Unauthorized,
+    NotFound(String),
+    PermissionDenied(String),
+    GraphQL(Vec<GraphQLError>),
+    Decode(serde_json::Error),
+    InvalidValue(String),
+    Transport(String),
+    Unrecorded(String),
+}
+
+impl PipefyError {
+    /// Sorts the `errors` of a response into the variant a caller can act on.
+    pub fn from_graphql(errors: Vec<GraphQLError>) -> PipefyError {
+        let mentions = |needle: &str| {
+            errors
+                .iter()
+                .any(|error| error.message.to_lowercase().contains(needle))
+        };
+        if mentions("not found") {
+            PipefyError::NotFound(messages(&errors))
+        } else if mentions("permission denied") {
+            PipefyError::PermissionDenied(messages(&errors))
+        } else {
+            PipefyError::GraphQL(errors)
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PipefyError::Network(_) => 3,
+            PipefyError::HttpStatus(_) => 4,
+            PipefyError::Unauthorized => 5,
+            PipefyError::NotFound(_) => 6,
+            PipefyError::PermissionDenied(_) => 7,
+            PipefyError::GraphQL(_) => 8,
+            PipefyError::Decode(_) => 9,
+            PipefyError::InvalidValue(_) => 10,
+            PipefyError::Transport(_) => 11,
+            PipefyError::Unrecorded(_) => 12,
+        }
+    }
+}
+
+fn messages(errors: &[GraphQLError]) -> String {
+    errors
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+impl fmt::Display for GraphQLError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            return write!(f, "{}", self.message);
+        }
+        let path = self
+            .path
+            .iter()
+            .map(|segment| match segment.as_str() {
+                Some(key) => key.to_string(),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(".");
+        write!(f, "{} (at {})", self.message, path)
+    }
+}
+
+impl fmt::Display for PipefyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipefyError::Network(e) => write!(f, "Could not reach Pipefy: {}", e),
+            PipefyError::HttpStatus(status) => {
+                write!(f, "Pipefy answered with HTTP status {}", status)
+            }
+            PipefyError::Unauthorized => write!(f, "Unauthorized, the API key was rejected"),
+            PipefyError::NotFound(what) => write!(f, "Not found: {}", what),
+            PipefyError::PermissionDenied(what) => write!(f, "Permission denied: {}", what),
+            PipefyError::GraphQL(errors) => {
+                write!(f, "Pipefy returned errors: {}", messages(errors))
+            }
+            PipefyError::Decode(e) => write!(f, "Could not understand Pipefy's answer: {}", e),
+            PipefyError::InvalidValue(why) => write!(f, "Invalid value: {}", why),
+            PipefyError::Transport(why) => write!(f, "Could not set up the HTTP client: {}", why),
+            PipefyError::Unrecorded(what) => write!(f, "No recorded response for {}", what),
+        }
+    }
+}
+
+impl error::Error for PipefyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PipefyError::Network(e) => Some(e),
+            PipefyError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for PipefyError {
+    fn from(e: reqwest::Error) -> PipefyError {
+        PipefyError::Network(e)
+    }
+}
+
+impl From<serde_json::Error> for PipefyError {
+    fn from(e: serde_json::Error) -> PipefyError {
+        PipefyError::Decode(e)
+    }
+}
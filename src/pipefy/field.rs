@@ -0,0 +1,307 @@
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A card field with its value parsed according to the field type.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(from = "RawCardField")]
+pub struct CardField {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub value: CardFieldValue,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CardFieldValue {
+    Empty,
+    ShortText(String),
+    LongText(String),
+    Number(f64),
+    Currency(f64),
+    Date(NaiveDate),
+    DateTime(DateTime<FixedOffset>),
+    DueDate(DateTime<FixedOffset>),
+    Select(String),
+    Checklist(Vec<String>),
+    Assignees(Vec<Named>),
+    Labels(Vec<Named>),
+    Attachments(Vec<String>),
+    Connections(Vec<String>),
+    /// Types we don't know about yet keep Pipefy's own text.
+    Other(String),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Named {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RawCardField {
+    name: String,
+    value: Option<String>,
+    array_value: Option<Vec<String>>,
+    date_value: Option<String>,
+    datetime_value: Option<String>,
+    float_value: Option<f64>,
+    assignee_values: Option<Vec<Named>>,
+    label_values: Option<Vec<Named>>,
+    field: RawFieldInfo,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RawFieldInfo {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+impl From<RawCardField> for CardField {
+    fn from(raw: RawCardField) -> CardField {
+        let value = CardFieldValue::parse(&raw);
+        CardField {
+            id: raw.field.id,
+            name: raw.name,
+            kind: raw.field.kind,
+            value,
+        }
+    }
+}
+
+impl CardFieldValue {
+    fn parse(raw: &RawCardField) -> CardFieldValue {
+        let text = raw.value.clone().unwrap_or_default();
+        let list = raw.array_value.clone().unwrap_or_default();
+        let number = raw.float_value.or_else(|| text.parse::<f64>().ok());
+        let datetime = raw
+            .datetime_value
+            .as_ref()
+            .and_then(|datetime| DateTime::parse_from_rfc3339(datetime).ok());
+        let parsed = match raw.field.kind.as_str() {
+            _ if text.is_empty() && list.is_empty() => Some(CardFieldValue::Empty),
+            "short_text" | "email" | "phone" => Some(CardFieldValue::ShortText(text.clone())),
+            "long_text" => Some(CardFieldValue::LongText(text.clone())),
+            "number" => number.map(CardFieldValue::Number),
+            "currency" => number.map(CardFieldValue::Currency),
+            "date" => raw
+                .date_value
+                .as_ref()
+                .and_then(|date| NaiveDate::parse_from_str(date.get(..10)?, "%Y-%m-%d").ok())
+                .map(CardFieldValue::Date),
+            "datetime" => datetime.map(CardFieldValue::DateTime),
+            "due_date" => datetime.map(CardFieldValue::DueDate),
+            "select" | "radio_vertical" | "radio_horizontal" => {
+                Some(CardFieldValue::Select(text.clone()))
+            }
+            "checklist_vertical" | "checklist_horizontal" => {
+                Some(CardFieldValue::Checklist(list.clone()))
+            }
+            "assignee_select" => raw.assignee_values.clone().map(CardFieldValue::Assignees),
+            "label_select" => raw.label_values.clone().map(CardFieldValue::Labels),
+            "attachment" => Some(CardFieldValue::Attachments(list.clone())),
+            "connector" => Some(CardFieldValue::Connections(list.clone())),
+            _ => None,
+        };
+        parsed.unwrap_or(CardFieldValue::Other(text))
+    }
+
+    /// The value written the way `FormField::parse_value` reads it back.
+    pub fn as_input(&self) -> String {
+        match self {
+            CardFieldValue::Empty => String::new(),
+            CardFieldValue::Date(date) => date.format("%Y-%m-%d").to_string(),
+            CardFieldValue::DateTime(datetime) | CardFieldValue::DueDate(datetime) => {
+                datetime.format("%Y-%m-%d %H:%M").to_string()
+            }
+            CardFieldValue::Assignees(named) | CardFieldValue::Labels(named) => named
+                .iter()
+                .map(|named| named.id.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+            value => value.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CardFieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardFieldValue::Empty => write!(f, ""),
+            CardFieldValue::ShortText(text)
+            | CardFieldValue::LongText(text)
+            | CardFieldValue::Select(text)
+            | CardFieldValue::Other(text) => write!(f, "{}", text),
+            CardFieldValue::Number(number) | CardFieldValue::Currency(number) => {
+                write!(f, "{}", number)
+            }
+            CardFieldValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            CardFieldValue::DateTime(datetime) | CardFieldValue::DueDate(datetime) => {
+                write!(f, "{}", datetime.format("%Y-%m-%d %H:%M"))
+            }
+            CardFieldValue::Checklist(items)
+            | CardFieldValue::Attachments(items)
+            | CardFieldValue::Connections(items) => write!(f, "{}", items.join(", ")),
+            CardFieldValue::Assignees(named) | CardFieldValue::Labels(named) => {
+                let names = named
+                    .iter()
+                    .map(|named| named.name.to_string())
+                    .collect::<Vec<String>>();
+                write!(f, "{}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for CardField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphql::FormField;
+    use serde_json::{json, Value};
+
+    fn parse(kind: &str, mut raw: Value) -> CardFieldValue {
+        raw["name"] = json!("Field");
+        raw["field"] = json!({"id": "field", "type": kind});
+        serde_json::from_value::<CardField>(raw).unwrap().value
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn dates_keep_only_the_day() {
+        let raw = json!({"value": "02/03/2020", "date_value": "2020-03-02T00:00:00-03:00"});
+        assert_eq!(parse("date", raw), CardFieldValue::Date(date("2020-03-02")));
+        let raw = json!({"value": "02/03/2020", "date_value": "2020-03-02"});
+        assert_eq!(parse("date", raw), CardFieldValue::Date(date("2020-03-02")));
+        let raw = json!({"value": "02/03/2020", "date_value": "2020"});
+        assert_eq!(
+            parse("date", raw),
+            CardFieldValue::Other("02/03/2020".to_string())
+        );
+    }
+
+    #[test]
+    fn numbers_prefer_float_value_over_the_text() {
+        let raw = json!({"value": "1.250,50", "float_value": 1250.5});
+        assert_eq!(parse("currency", raw), CardFieldValue::Currency(1250.5));
+        assert_eq!(
+            parse("number", json!({"value": "7"})),
+            CardFieldValue::Number(7.0)
+        );
+        assert_eq!(
+            parse("number", json!({"value": "seven"})),
+            CardFieldValue::Other("seven".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_values_are_empty_whatever_the_type() {
+        assert_eq!(parse("number", json!({"value": ""})), CardFieldValue::Empty);
+        assert_eq!(parse("short_text", json!({})), CardFieldValue::Empty);
+        let raw = json!({"value": "[]", "array_value": []});
+        assert_eq!(
+            parse("checklist_vertical", raw),
+            CardFieldValue::Checklist(Vec::new())
+        );
+        let raw = json!({"value": null, "array_value": []});
+        assert_eq!(parse("checklist_vertical", raw), CardFieldValue::Empty);
+    }
+
+    #[test]
+    fn lists_come_from_array_value() {
+        let raw = json!({"value": "[\"Signed\", \"Paid\"]", "array_value": ["Signed", "Paid"]});
+        assert_eq!(
+            parse("checklist_horizontal", raw),
+            CardFieldValue::Checklist(vec!["Signed".to_string(), "Paid".to_string()])
+        );
+        let raw = json!({
+            "value": "[\"Ana Souza\"]",
+            "assignee_values": [{"id": "7", "name": "Ana Souza"}]
+        });
+        assert_eq!(
+            parse("assignee_select", raw),
+            CardFieldValue::Assignees(vec![Named {
+                id: "7".to_string(),
+                name: "Ana Souza".to_string()
+            }])
+        );
+    }
+
+    #[test]
+    fn unknown_types_keep_the_text() {
+        assert_eq!(
+            parse("formula", json!({"value": "42"})),
+            CardFieldValue::Other("42".to_string())
+        );
+        assert_eq!(
+            parse(
+                "datetime",
+                json!({"value": "soon", "datetime_value": "soon"})
+            ),
+            CardFieldValue::Other("soon".to_string())
+        );
+    }
+
+    #[test]
+    fn as_input_reads_back_through_parse_value() {
+        let cases = vec![
+            ("number", parse("number", json!({"value": "12.5"}))),
+            ("date", CardFieldValue::Date(date("2020-02-29"))),
+            (
+                "due_date",
+                parse(
+                    "due_date",
+                    json!({"value": "x", "datetime_value": "2020-03-02T14:30:00-03:00"}),
+                ),
+            ),
+            (
+                "checklist_vertical",
+                CardFieldValue::Checklist(vec!["Signed".to_string(), "Paid".to_string()]),
+            ),
+            (
+                "label_select",
+                CardFieldValue::Labels(vec![Named {
+                    id: "501".to_string(),
+                    name: "Urgent".to_string(),
+                }]),
+            ),
+            ("short_text", CardFieldValue::Empty),
+        ];
+        for (kind, value) in cases {
+            let form_field = FormField {
+                id: "field".to_string(),
+                label: "Field".to_string(),
+                kind: kind.to_string(),
+                required: false,
+                options: vec!["Signed".to_string(), "Paid".to_string()],
+                description: None,
+            };
+            let input = value.as_input();
+            assert!(
+                form_field.parse_value(&input).is_ok(),
+                "{} rejected {:?}",
+                kind,
+                input
+            );
+        }
+        assert_eq!(
+            parse(
+                "due_date",
+                json!({"value": "x", "datetime_value": "2020-03-02T14:30:00-03:00"})
+            )
+            .as_input(),
+            "2020-03-02 14:30"
+        );
+    }
+}
@@ -1,389 +1,806 @@
-use crate::user::{User, UserInfo};
-use prettyprint::PrettyPrinter;
-use serde::de::{self, Deserializer};
-use serde::Deserialize;
-use serde_json::Value;
-use std::collections::HashMap;
-use std::error::Error;
+use crate::client::{decode, PipefyClient};
+use crate::error::PipefyError;
+use crate::field::{CardField, Named};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
 use std::fmt::Display;
+use std::marker::PhantomData;
 use std::str::FromStr;
-use std::{error, fmt};
 
-#[derive(Debug, Clone)]
-struct Unauthorized;
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+pub const ME_QUERY: &str = "query {
+    me {
+        id
+        name
+    }
+}";
+
+pub const CARD_QUERY: &str = "query($id: ID!) {
+    card(id: $id) {
+        id
+        title
+        url
+        current_phase {
+            id
+            name
+        }
+        fields {
+            name
+            value
+            array_value
+            date_value
+            datetime_value
+            float_value
+            assignee_values {
+                id
+                name
+            }
+            label_values {
+                id
+                name
+            }
+            field {
+                id
+                type
+            }
+        }
+    }
+}";
+
+pub const PIPE_QUERY: &str = "query($id: ID!) {
+    pipe(id: $id) {
+        id
+        name
+        phases {
+            id
+            name
+            cards_count
+            description
+            done
+        }
+    }
+}";
+
+pub const PHASE_QUERY: &str = "query($id: ID!) {
+    phase(id: $id) {
+        id
+        name
+        cards_count
+        description
+        done
+        fields {
+            id
+            label
+            type
+            required
+            options
+            description
+        }
+    }
+}";
+
+pub const PHASE_CARDS_QUERY: &str = "query($id: ID!, $first: Int, $after: String) {
+    phase(id: $id) {
+        cards(first: $first, after: $after) {
+            edges {
+                node {
+                    id
+                    url
+                    title
+                    current_phase {
+                        id
+                        name
+                    }
+                    fields {
+                        name
+                        value
+                        array_value
+                        date_value
+                        datetime_value
+                        float_value
+                        assignee_values {
+                            id
+                            name
+                        }
+                        label_values {
+                            id
+                            name
+                        }
+                        field {
+                            id
+                            type
+                        }
+                    }
+                }
+            }
+            pageInfo {
+                hasNextPage
+                endCursor
+            }
+        }
+    }
+}";
+
+pub const ORGANIZATION_QUERY: &str = "query($id: ID!) {
+    organization(id: $id) {
+        id
+        name
+        members {
+            user {
+                id
+                name
+            }
+            role_name
+        }
+    }
+}";
+
+pub const ORGANIZATION_PIPES_QUERY: &str = "query($id: ID!) {
+    me {
+        id
+        name
+    }
+    organization(id: $id) {
+        pipes {
+            id
+            name
+            cards_count
+            public
+            phases {
+                id
+                name
+            }
+            members {
+                user {
+                    id
+                    name
+                }
+                role_name
+            }
+        }
+    }
+}";
+
+pub const PIPE_CARDS_QUERY: &str = "query($pipeId: ID!, $first: Int, $after: String) {
+    allCards(pipeId: $pipeId, first: $first, after: $after) {
+        edges {
+            node {
+                id
+                url
+                title
+                due_date
+                current_phase {
+                    id
+                    name
+                }
+                labels {
+                    id
+                    name
+                }
+                assignees {
+                    id
+                    name
+                }
+                fields {
+                    name
+                    value
+                    array_value
+                    date_value
+                    datetime_value
+                    float_value
+                    assignee_values {
+                        id
+                        name
+                    }
+                    label_values {
+                        id
+                        name
+                    }
+                    field {
+                        id
+                        type
+                    }
+                }
+            }
+        }
+        pageInfo {
+            hasNextPage
+            endCursor
+        }
+    }
+}";
+
+pub const SEARCH_CARDS_QUERY: &str =
+    "query($pipeId: ID!, $search: CardSearch, $first: Int, $after: String) {
+    cards(pipe_id: $pipeId, search: $search, first: $first, after: $after) {
+        edges {
+            node {
+                id
+                url
+                title
+                created_at
+                updated_at
+                due_date
+                current_phase {
+                    id
+                    name
+                }
+                labels {
+                    id
+                    name
+                }
+                assignees {
+                    id
+                    name
+                }
+                fields {
+                    name
+                    value
+                    array_value
+                    date_value
+                    datetime_value
+                    float_value
+                    assignee_values {
+                        id
+                        name
+                    }
+                    label_values {
+                        id
+                        name
+                    }
+                    field {
+                        id
+                        type
+                    }
+                }
+            }
+        }
+        pageInfo {
+            hasNextPage
+            endCursor
+        }
+    }
+}";
+
+pub const START_FORM_QUERY: &str = "query($id: ID!) {
+    pipe(id: $id) {
+        start_form_fields {
+            id
+            label
+            type
+            required
+            options
+            description
+        }
+    }
+}";
+
+pub const CREATE_CARD_MUTATION: &str = "mutation($input: CreateCardInput!) {
+    createCard(input: $input) {
+        card {
+            id
+            title
+            url
+        }
+    }
+}";
+
+pub const EDITABLE_FIELDS_QUERY: &str = "query($id: ID!) {
+    card(id: $id) {
+        pipe {
+            start_form_fields {
+                id
+                label
+                type
+                required
+                options
+                description
+            }
+        }
+        current_phase {
+            fields {
+                id
+                label
+                type
+                required
+                options
+                description
+            }
+        }
+    }
+}";
+
+pub const UPDATE_CARD_FIELD_MUTATION: &str = "mutation($input: UpdateCardFieldInput!) {
+    updateCardField(input: $input) {
+        card {
+            id
+            title
+            url
+            current_phase {
+                id
+                name
+            }
+            fields {
+                name
+                value
+                array_value
+                date_value
+                datetime_value
+                float_value
+                assignee_values {
+                    id
+                    name
+                }
+                label_values {
+                    id
+                    name
+                }
+                field {
+                    id
+                    type
+                }
+            }
+        }
+    }
+}";
+
+pub const MOVE_TARGETS_QUERY: &str = "query($id: ID!) {
+    card(id: $id) {
+        current_phase {
+            name
+            cards_can_be_moved_to_phases {
+                id
+                name
+            }
+        }
+    }
+}";
+
+pub const MOVE_CARD_MUTATION: &str = "mutation($input: MoveCardToPhaseInput!) {
+    moveCardToPhase(input: $input) {
+        card {
+            id
+            title
+            url
+            current_phase {
+                id
+                name
+            }
+        }
+    }
+}";
 
 #[derive(Deserialize, Debug, Clone)]
-pub struct CardNode {
-    pub node: Card,
+struct Edge<T> {
+    node: T,
 }
+
 #[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+/// Walks a GraphQL connection page by page, following `pageInfo.endCursor`
+/// until the API says there is nothing left or `limit` nodes were fetched.
+pub struct Connection<'a, T> {
+    client: &'a PipefyClient,
+    query: &'static str,
+    variables: Value,
+    path: &'static [&'static str],
+    page_size: usize,
+    limit: Option<usize>,
+    fetched: usize,
+    cursor: Option<String>,
+    finished: bool,
+    node: PhantomData<T>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Card {
     pub title: String,
     #[serde(deserialize_with = "from_str")]
     pub id: usize,
     pub url: Option<String>,
-    pub fields: Option<Vec<Value>>,
+    pub current_phase: Option<PhaseRef>,
+    // Only asked for where they are filtered on or previewed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<FixedOffset>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<FixedOffset>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<DateTime<FixedOffset>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<Named>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assignees: Vec<Named>,
+    #[serde(default)]
+    pub fields: Vec<CardField>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PhaseRef {
+    #[serde(deserialize_with = "from_str")]
+    pub id: usize,
+    pub name: String,
+}
+
+/// The phase a card sits in and the phases it is allowed to go to.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PhaseMoves {
+    pub name: String,
+    pub cards_can_be_moved_to_phases: Vec<PhaseRef>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
+pub struct MoveTargets {
+    pub current_phase: PhaseMoves,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Phase {
+    #[serde(deserialize_with = "from_str")]
+    pub id: usize,
+    pub name: String,
+    pub cards_count: u32,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default)]
+    pub fields: Vec<FormField>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Pipe {
+    #[serde(deserialize_with = "from_str")]
+    pub id: usize,
+    pub name: String,
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Organization {
+    #[serde(deserialize_with = "from_str")]
+    pub id: usize,
+    pub name: String,
+    #[serde(default)]
+    pub members: Vec<Member>,
+}
+
+/// A pipe as listed under its organization.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OrgPipe {
+    #[serde(deserialize_with = "from_str")]
+    pub id: usize,
     pub name: String,
+    #[serde(skip_deserializing)]
+    pub phases_count: usize,
+    #[serde(default)]
     pub cards_count: u32,
-    pub description: String,
+    #[serde(default)]
+    pub public: bool,
+    #[serde(skip_deserializing)]
+    pub member: bool,
+    #[serde(default, skip_serializing)]
+    pub phases: Vec<PhaseRef>,
+    #[serde(default, skip_serializing)]
+    pub members: Vec<Member>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct OrgPipes {
+    pub pipes: Vec<OrgPipe>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Member {
+    pub user: Person,
+    pub role_name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Person {
+    #[serde(deserialize_with = "from_str")]
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FormField {
+    pub id: String,
+    pub label: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub required: bool,
+    #[serde(default)]
+    pub options: Vec<String>,
+    pub description: Option<String>,
+}
+
+/// A value to write into a card field, as the mutations expect it.
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldValue {
+    pub field_id: String,
+    pub field_value: Value,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StartForm {
+    pub start_form_fields: Vec<FormField>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PhaseFields {
+    pub fields: Vec<FormField>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EditableFields {
+    pub pipe: StartForm,
+    pub current_phase: PhaseFields,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CardPayload {
+    pub card: Card,
+}
+
+impl PhaseMoves {
+    /// Finds an allowed destination by id or by name.
+    pub fn destination(&self, id_or_name: &str) -> Option<&PhaseRef> {
+        self.cards_can_be_moved_to_phases.iter().find(|phase| {
+            phase.id.to_string() == id_or_name
+                || phase.name.to_lowercase() == id_or_name.to_lowercase()
+        })
+    }
+}
+
+impl FormField {
+    /// Fields can be referred to by their id or by their label.
+    pub fn is_named(&self, name: &str) -> bool {
+        self.id == name || self.label.to_lowercase() == name.to_lowercase()
+    }
+
+    /// Checks `input` against the field type and turns it into the value
+    /// `updateCardField` expects. Lists are comma separated, and an empty
+    /// input clears an optional field.
+    pub fn parse_value(&self, input: &str) -> Result<Value, PipefyError> {
+        let input = input.trim();
+        let invalid = |why: String| Err(PipefyError::InvalidValue(why));
+        if input.is_empty() {
+            return if self.required {
+                invalid(format!("{} is required", self.label))
+            } else {
+                Ok(Value::Null)
+            };
+        }
+        match self.kind.as_str() {
+            "number" | "currency" => match input.parse::<f64>() {
+                Ok(_) => Ok(json!(input)),
+                Err(_) => invalid(format!("{} expects a number, got {}", self.label, input)),
+            },
+            "date" => match NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+                Ok(_) => Ok(json!(input)),
+                Err(_) => invalid(format!("{} expects a YYYY-MM-DD date", self.label)),
+            },
+            "datetime" | "due_date" => {
+                let parsed = NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M")
+                    .map(|_| ())
+                    .or_else(|_| DateTime::parse_from_rfc3339(input).map(|_| ()));
+                match parsed {
+                    Ok(_) => Ok(json!(input)),
+                    Err(_) => invalid(format!(
+                        "{} expects a YYYY-MM-DD HH:MM date and time",
+                        self.label
+                    )),
+                }
+            }
+            // Without options to check against, any text goes
+            "select" | "radio_vertical" | "radio_horizontal" if !self.options.is_empty() => {
+                match self.options.iter().find(|option| option.as_str() == input) {
+                    Some(option) => Ok(json!(option)),
+                    None => invalid(format!(
+                        "{} expects one of {}",
+                        self.label,
+                        self.options.join(", ")
+                    )),
+                }
+            }
+            "checklist_vertical" | "checklist_horizontal" if !self.options.is_empty() => {
+                let items = split_list(input);
+                match items.iter().find(|item| !self.options.contains(item)) {
+                    Some(item) => invalid(format!(
+                        "{} is not an option of {}, expected some of {}",
+                        item,
+                        self.label,
+                        self.options.join(", ")
+                    )),
+                    None => Ok(json!(items)),
+                }
+            }
+            "assignee_select" | "label_select" => {
+                let ids = split_list(input);
+                match ids.iter().find(|id| id.parse::<u64>().is_err()) {
+                    Some(id) => invalid(format!("{} expects ids, got {}", self.label, id)),
+                    None => Ok(json!(ids)),
+                }
+            }
+            _ => Ok(json!(input)),
+        }
+    }
+}
+
+fn split_list(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
 }
 
 impl Display for Card {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let print_url = &self.url.as_ref().map_or("", |url| url);
-        let default_fields = Vec::new();
-        let fields = self.fields.as_ref().map_or(&default_fields, |f| f);
-        let printable_fields = fields
-            .iter()
-            .fold(String::new(), |acc, arg| acc + &arg.to_string() + ",\n");
+        let print_phase = &self.current_phase.as_ref().map_or("", |phase| &phase.name);
+        let printable_fields = self.fields.iter().fold(String::new(), |acc, field| {
+            acc + "\n  " + &field.to_string()
+        });
         write!(
             f,
-            "Title: {},\nid: {},\nurl: {},\nfields: {}",
-            self.title, self.id, print_url, printable_fields
+            "Title: {},\nid: {},\nurl: {},\nphase: {},\nfields: {}",
+            self.title, self.id, print_url, print_phase, printable_fields
         )
     }
 }
 
-impl Unauthorized {
-    fn new() -> Unauthorized {
-        Unauthorized {}
+impl Card {
+    pub fn fields_summary(&self) -> String {
+        self.fields
+            .iter()
+            .map(|field| field.to_string())
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+
+    /// Looks a field up by its id or its name.
+    pub fn field(&self, id_or_name: &str) -> Option<&CardField> {
+        self.fields.iter().find(|field| {
+            field.id == id_or_name || field.name.to_lowercase() == id_or_name.to_lowercase()
+        })
     }
 }
 
-impl fmt::Display for Unauthorized {
+impl Display for Phase {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Unauthorized access")
+        let printable_fields = self
+            .fields
+            .iter()
+            .map(|field| format!("{} ({})", field.label, field.kind))
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(
+            f,
+            "Name: {},\nid: {},\ncards_count: {},\ndone: {},\ndescription: {},\nfields: {}",
+            self.name,
+            self.id,
+            self.cards_count,
+            self.done,
+            self.description
+                .as_ref()
+                .map_or("", |description| description),
+            printable_fields
+        )
     }
 }
 
-impl error::Error for Unauthorized {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        None
+impl Display for Pipe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
     }
 }
-pub fn me_query(api_key: &str) -> Result<User, Box<Error>> {
-    let mut query = HashMap::new();
-    query.insert("query", String::from("query { me { name id } }"));
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    let name = response_body["data"]["me"]["name"].to_owned();
-    let string_id = serde_json::from_value::<String>(response_body["data"]["me"]["id"].to_owned());
-    match string_id {
-        Ok(string_id) => {
-            let user_info = UserInfo {
-                name: name.to_string().replace("\"", ""),
-                id: string_id.parse().unwrap(),
-            };
-            let user = User {
-                api_key: api_key.to_string(),
-                info: user_info,
-            };
-            Ok(user)
-        }
-        Err(e) => {
-            println!("{}", e);
-            Err(Box::new(Unauthorized::new()))
-        }
+
+impl Display for Organization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
     }
 }
 
-pub fn pipe_cards_select(api_key: &str, pipe_id: i32) -> Result<Vec<CardNode>, Box<Error>> {
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_pipe_cards_query_string = format!(
-        "{{
-            allCards(pipeId: {id}) {{
-                edges {{
-                node {{
-                    id
-                    url
-                    title
-                    fields {{
-                        name
-                        value
-                    }}
-                }}
-                }}
-            }}
-        }}",
-        id = pipe_id
-    );
-    let pipe_cards_query_string = String::from(format_pipe_cards_query_string);
-    query.insert("query", pipe_cards_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    let cards = serde_json::from_value::<Vec<CardNode>>(
-        response_body["data"]["allCards"]["edges"].to_owned(),
-    );
-    match cards {
-        Ok(cards) => Ok(cards),
-        _ => Err(Box::new(Unauthorized::new())),
-    }
-}
-pub fn pipe_cards_query(api_key: &str, pipe_id: i32) -> Result<(), Box<Error>> {
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
-        .unwrap();
-
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_pipe_cards_query_string = format!(
-        "{{
-            allCards(pipeId: {id}) {{
-                edges {{
-                    node {{
-                        id
-                        url
-                        title
-                        fields {{
-                            name
-                            value
-                        }}
-                    }}
-                }}
-            }}
-        }}",
-        id = pipe_id
-    );
-    let pipe_cards_query_string = String::from(format_pipe_cards_query_string);
-    query.insert("query", pipe_cards_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    let cards = serde_json::from_value::<Vec<CardNode>>(
-        response_body["data"]["allCards"]["edges"].to_owned(),
-    );
-    match cards {
-        Ok(cards) => {
-            let cards_as_string = cards.iter().fold("".to_string(), |acc, card_node| {
-                acc + "Card \n" + &card_node.node.to_string() + "\n"
-            });
-            print.string_with_header(cards_as_string, "Cards".to_string())?;
-            Ok(())
-        }
-        _ => Err(Box::new(Unauthorized::new())),
+impl Display for OrgPipe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Name: {},\nid: {},\nphases: {},\ncards: {},\npublic: {},\nmember: {}",
+            self.name, self.id, self.phases_count, self.cards_count, self.public, self.member
+        )
     }
 }
 
-pub fn card_query_and_print(api_key: &str, card_id: i32) -> Result<(), Box<Error>> {
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
-        .unwrap();
-
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_card_query_string = format!(
-        "query {{
-        card(id: {id}) {{
-            id
-            title
-            url
-            fields {{
-                value
-                name
-    }} }} }}",
-        id = card_id
-    );
-    let card_query_string = String::from(format_card_query_string);
-    query.insert("query", card_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    let card = serde_json::from_value::<Card>(response_body["data"]["card"].to_owned());
-    match card {
-        Ok(card) => {
-            print.string_with_header(card.to_string(), "Card".to_string())?;
-            Ok(())
-        }
-        _ => Err(Box::new(Unauthorized::new())),
+impl Display for Member {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Name: {},\nid: {},\nrole: {}",
+            self.user.name, self.user.id, self.role_name
+        )
     }
 }
 
-pub fn pipe_name_query(api_key: &str, pipe_id: i32) -> Result<(), Box<Error>> {
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
-        .unwrap();
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_pipe_query_string = format!(
-        "query {{
-        pipe(id: {id}) {{
-            name
-    }} }}",
-        id = pipe_id
-    );
-    let pipe_query_string = String::from(format_pipe_query_string);
-    query.insert("query", pipe_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    match &response_body["data"]["pipe"]["name"] {
-        serde_json::Value::String(response) => {
-            print.string_with_header(response.to_string(), "Pipe Name".to_string())?;
-            Ok(())
-        }
-        _ => Err(Box::new(Unauthorized::new())),
-    }
-}
-
-pub fn pipe_phases_query(api_key: &str, pipe_id: i32) -> Result<(), Box<Error>> {
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
-        .unwrap();
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_pipe_query_string = format!(
-        "query {{
-        pipe(id: {id}) {{
-            phases {{
-                name
-                cards_count
-                description
-            }}
-    }} }}",
-        id = pipe_id
-    );
-    let pipe_query_string = String::from(format_pipe_query_string);
-    query.insert("query", pipe_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    match &response_body["data"]["pipe"]["phases"] {
-        serde_json::Value::Array(_) => {
-            let phases =
-                serde_json::to_string_pretty(&response_body["data"]["pipe"]["phases"]).unwrap();
-            print.string_with_header(phases, "Phases".to_string())?;
-            Ok(())
+impl<'a, T: DeserializeOwned> Connection<'a, T> {
+    pub fn new(
+        client: &'a PipefyClient,
+        query: &'static str,
+        variables: Value,
+        path: &'static [&'static str],
+        page_size: usize,
+        limit: Option<usize>,
+    ) -> Connection<'a, T> {
+        Connection {
+            client,
+            query,
+            variables,
+            path,
+            page_size,
+            limit,
+            fetched: 0,
+            cursor: None,
+            finished: false,
+            node: PhantomData,
         }
-        _ => Err(Box::new(Unauthorized::new())),
+    }
+
+    fn fetch_page(&self, first: usize) -> Result<(Vec<T>, PageInfo), PipefyError> {
+        let mut variables = self.variables.clone();
+        variables["first"] = json!(first);
+        variables["after"] = json!(self.cursor);
+        let data = self.client.request(self.query, variables)?;
+        let connection = self.path.iter().fold(&data, |value, key| &value[*key]);
+        let edges: Vec<Edge<T>> = decode(&connection["edges"])?;
+        let page_info: PageInfo = decode(&connection["pageInfo"])?;
+        Ok((edges.into_iter().map(|edge| edge.node).collect(), page_info))
     }
 }
 
-pub fn organization_name_query(api_key: &str, org_id: i32) -> Result<(), Box<Error>> {
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
-        .unwrap();
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_org_query_string = format!(
-        "query {{
-        organization(id: {id}) {{
-            name
-        }} }}",
-        id = org_id
-    );
-    let org_query_string = String::from(format_org_query_string);
-    query.insert("query", org_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    match &response_body["data"]["organization"]["name"] {
-        serde_json::Value::String(response) => {
-            print.string_with_header(response.to_string(), "Organization Name".to_string())?;
-            Ok(())
+impl<'a, T: DeserializeOwned> Iterator for Connection<'a, T> {
+    type Item = Result<Vec<T>, PipefyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
         }
-        _ => Err(Box::new(Unauthorized::new())),
-    }
-}
-
-pub fn org_pipes_query(api_key: &str, org_id: i32) -> Result<(), Box<Error>> {
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
-        .unwrap();
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_org_query_string = format!(
-        "{{
-            organization(id: {id})
-            {{
-                members {{
-                user {{
-                    id
-                    name
-                }}
-                role_name
-                }}
-                name
-            }}
-        }} ",
-        id = org_id
-    );
-    let org_query_string = String::from(format_org_query_string);
-    query.insert("query", org_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    match &response_body["data"]["organization"]["members"] {
-        serde_json::Value::Array(response) => {
-            let members =
-                serde_json::to_string_pretty(&response_body["data"]["organization"]["members"])
-                    .unwrap();
-            print.string_with_header(members, "Members".to_string())?;
-            Ok(())
+        let first = match self.limit {
+            Some(limit) => self.page_size.min(limit.saturating_sub(self.fetched)),
+            None => self.page_size,
+        };
+        if first == 0 {
+            self.finished = true;
+            return None;
         }
-        _ => Err(Box::new(Unauthorized::new())),
-    }
-}
-pub fn org_members_query(api_key: &str, org_id: i32) -> Result<(), Box<Error>> {
-    let print = PrettyPrinter::default()
-        .language("rust")
-        .grid(true)
-        .line_numbers(true)
-        .build()
-        .unwrap();
-    let mut query: HashMap<&str, String> = HashMap::new();
-    let format_org_query_string = format!(
-        "{{
-            organization(id: {id})
-            {{
-                members {{
-                user {{
-                    id
-                    name
-                }}
-                role_name
-                }}
-                name
-            }}
-        }} ",
-        id = org_id
-    );
-    let org_query_string = String::from(format_org_query_string);
-    query.insert("query", org_query_string);
-    let text_response = perform_query(api_key, query)?;
-    let response_body: Value = serde_json::from_str(&text_response)?;
-    match &response_body["data"]["organization"]["members"] {
-        serde_json::Value::Array(response) => {
-            let members =
-                serde_json::to_string_pretty(&response_body["data"]["organization"]["members"])
-                    .unwrap();
-            print.string_with_header(members, "Members".to_string())?;
-            Ok(())
+        match self.fetch_page(first) {
+            Ok((mut nodes, page_info)) => {
+                // Pipefy may send more than `first`, the limit still holds
+                if let Some(limit) = self.limit {
+                    nodes.truncate(limit.saturating_sub(self.fetched));
+                }
+                self.fetched += nodes.len();
+                self.finished = !page_info.has_next_page || page_info.end_cursor.is_none();
+                self.cursor = page_info.end_cursor;
+                Some(Ok(nodes))
+            }
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
         }
-        _ => Err(Box::new(Unauthorized::new())),
     }
 }
 
-fn perform_query(api_key: &str, query: HashMap<&str, String>) -> Result<String, reqwest::Error> {
-    let client = reqwest::Client::new();
-    let mut res = client
-        .post("https://app.pipefy.com/queries")
-        .json(&query)
-        .bearer_auth(api_key)
-        .send()?;
-
-    res.text()
-}
-
 pub fn from_str<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
     T: FromStr,
@@ -393,3 +810,97 @@ where
     let s = String::deserialize(deserializer)?;
     T::from_str(&s).map_err(de::Error::custom)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn form_field(kind: &str, required: bool, options: &[&str]) -> FormField {
+        FormField {
+            id: "field".to_string(),
+            label: "Field".to_string(),
+            kind: kind.to_string(),
+            required,
+            options: options.iter().map(|option| option.to_string()).collect(),
+            description: None,
+        }
+    }
+
+    fn assert_invalid(result: Result<Value, PipefyError>) {
+        match result {
+            Err(PipefyError::InvalidValue(_)) => (),
+            other => panic!("expected InvalidValue, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn empty_input_clears_only_optional_fields() {
+        assert_eq!(
+            form_field("short_text", false, &[])
+                .parse_value("  ")
+                .unwrap(),
+            Value::Null
+        );
+        assert_invalid(form_field("short_text", true, &[]).parse_value(""));
+    }
+
+    #[test]
+    fn numbers_and_dates_are_checked() {
+        let number = form_field("number", false, &[]);
+        assert_eq!(number.parse_value("12.5").unwrap(), json!("12.5"));
+        assert_invalid(number.parse_value("twelve"));
+        let date = form_field("date", false, &[]);
+        assert_eq!(date.parse_value("2020-02-29").unwrap(), json!("2020-02-29"));
+        assert_invalid(date.parse_value("2020-02-30"));
+        let datetime = form_field("due_date", false, &[]);
+        assert!(datetime.parse_value("2020-03-02 14:30").is_ok());
+        assert!(datetime.parse_value("2020-03-02T14:30:00-03:00").is_ok());
+        assert_invalid(datetime.parse_value("2020-03-02"));
+    }
+
+    #[test]
+    fn options_must_exist() {
+        let select = form_field("select", false, &["Low", "High"]);
+        assert_eq!(select.parse_value("High").unwrap(), json!("High"));
+        assert_invalid(select.parse_value("Medium"));
+        let checklist = form_field("checklist_vertical", false, &["Signed", "Paid"]);
+        assert_eq!(
+            checklist.parse_value("Signed, Paid").unwrap(),
+            json!(["Signed", "Paid"])
+        );
+        assert_invalid(checklist.parse_value("Signed, Lost"));
+    }
+
+    #[test]
+    fn fields_without_options_take_any_text() {
+        assert_eq!(
+            form_field("select", true, &[])
+                .parse_value("Other")
+                .unwrap(),
+            json!("Other")
+        );
+        assert_eq!(
+            form_field("checklist_vertical", true, &[])
+                .parse_value("Signed")
+                .unwrap(),
+            json!("Signed")
+        );
+    }
+
+    #[test]
+    fn assignees_and_labels_take_ids() {
+        let assignees = form_field("assignee_select", false, &[]);
+        assert_eq!(assignees.parse_value("7, 8").unwrap(), json!(["7", "8"]));
+        assert_invalid(assignees.parse_value("7, Ana"));
+    }
+
+    #[test]
+    fn other_types_keep_the_trimmed_text() {
+        assert_eq!(
+            form_field("long_text", true, &[])
+                .parse_value(" Call back ")
+                .unwrap(),
+            json!("Call back")
+        );
+    }
+}
@@ -1,2 +1,9 @@
+pub mod client;
+pub mod error;
+pub mod field;
 pub mod graphql;
+pub mod search;
+pub mod trace;
 pub mod user;
+pub mod watch;
+pub mod webhook;
@@ -0,0 +1,266 @@
+use crate::error::PipefyError;
+use crate::field::Named;
+use crate::graphql::{Card, Connection};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::Serialize;
+
+/// What a card search looks for. Pipefy filters titles, and labels or
+/// assignees given by id; everything is checked again on each card, so
+/// names and the filters Pipefy lacks work as well.
+#[derive(Debug, Clone, Default)]
+pub struct CardFilter {
+    pub title: Option<String>,
+    /// Phase id or name.
+    pub phase: Option<String>,
+    /// Label ids or names, a card needs any of them.
+    pub labels: Vec<String>,
+    /// Assignee ids or names, a card needs any of them.
+    pub assignees: Vec<String>,
+    pub created: DateRange,
+    pub updated: DateRange,
+    pub due: DateRange,
+    /// Field id or name and the value it must have.
+    pub fields: Vec<(String, String)>,
+}
+
+/// The pages of a card search, keeping only the cards that pass the filter.
+/// No page is fetched once `limit` matching cards were found.
+pub struct SearchPages<'a> {
+    pages: Connection<'a, Card>,
+    filter: &'a CardFilter,
+    remaining: Option<usize>,
+}
+
+/// Days a date may fall in, both ends included.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+/// The `search` argument of Pipefy's `cards` query.
+#[derive(Serialize, Debug)]
+pub struct CardSearch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    label_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    assignee_ids: Vec<String>,
+    include_done: bool,
+}
+
+impl<'a> SearchPages<'a> {
+    pub fn new(
+        pages: Connection<'a, Card>,
+        filter: &'a CardFilter,
+        limit: Option<usize>,
+    ) -> SearchPages<'a> {
+        SearchPages {
+            pages,
+            filter,
+            remaining: limit,
+        }
+    }
+}
+
+impl<'a> Iterator for SearchPages<'a> {
+    type Item = Result<Vec<Card>, PipefyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        let page = self.pages.next()?;
+        Some(page.map(|cards| {
+            let mut cards: Vec<Card> = cards
+                .into_iter()
+                .filter(|card| self.filter.matches(card))
+                .collect();
+            if let Some(remaining) = self.remaining.as_mut() {
+                cards.truncate(*remaining);
+                *remaining -= cards.len();
+            }
+            cards
+        }))
+    }
+}
+
+impl CardFilter {
+    /// The part of the filter Pipefy can apply itself.
+    pub fn search(&self) -> CardSearch {
+        CardSearch {
+            title: self.title.clone(),
+            label_ids: only_ids(&self.labels),
+            assignee_ids: only_ids(&self.assignees),
+            include_done: true,
+        }
+    }
+
+    pub fn matches(&self, card: &Card) -> bool {
+        let title = self.title.as_ref().map_or(true, |title| {
+            card.title.to_lowercase().contains(&title.to_lowercase())
+        });
+        let phase = self.phase.as_ref().map_or(true, |phase| {
+            card.current_phase.as_ref().map_or(false, |current| {
+                current.id.to_string() == *phase || same_name(&current.name, phase)
+            })
+        });
+        let field = self.fields.iter().all(|(name, value)| {
+            card.field(name).map_or(false, |field| {
+                same_name(&field.value.to_string(), value)
+                    || same_name(&field.value.as_input(), value)
+            })
+        });
+        title
+            && phase
+            && any_named(&card.labels, &self.labels)
+            && any_named(&card.assignees, &self.assignees)
+            && self.created.contains(card.created_at)
+            && self.updated.contains(card.updated_at)
+            && self.due.contains(card.due_date)
+            && field
+    }
+}
+
+impl DateRange {
+    pub fn is_set(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
+
+    /// Whether `date` is in the range. Cards without the date only match
+    /// when no range was asked for.
+    pub fn contains(&self, date: Option<DateTime<FixedOffset>>) -> bool {
+        if !self.is_set() {
+            return true;
+        }
+        date.map_or(false, |date| {
+            let day = date.naive_local().date();
+            self.from.map_or(true, |from| day >= from) && self.to.map_or(true, |to| day <= to)
+        })
+    }
+}
+
+/// Pipefy only takes ids, so names keep the whole list client side.
+fn only_ids(values: &[String]) -> Vec<String> {
+    if values.iter().all(|value| value.parse::<u64>().is_ok()) {
+        values.to_vec()
+    } else {
+        Vec::new()
+    }
+}
+
+fn any_named(named: &[Named], wanted: &[String]) -> bool {
+    wanted.is_empty()
+        || wanted.iter().any(|wanted| {
+            named
+                .iter()
+                .any(|named| named.id == *wanted || same_name(&named.name, wanted))
+        })
+}
+
+fn same_name(name: &str, other: &str) -> bool {
+    name.trim().to_lowercase() == other.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn card() -> Card {
+        serde_json::from_value(json!({
+            "id": "1001",
+            "title": "ACME renewal",
+            "url": null,
+            "current_phase": {"id": "3001", "name": "Backlog"},
+            "created_at": "2020-03-02T23:30:00-03:00",
+            "labels": [{"id": "501", "name": "Urgent"}],
+            "assignees": [{"id": "7", "name": "Ana Souza"}],
+            "fields": [{
+                "name": "Customer",
+                "value": "ACME",
+                "field": {"id": "customer", "type": "short_text"}
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn day(date: &str) -> Option<NaiveDate> {
+        Some(NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap())
+    }
+
+    #[test]
+    fn an_empty_filter_matches_everything() {
+        assert!(CardFilter::default().matches(&card()));
+    }
+
+    #[test]
+    fn title_and_phase_ignore_case() {
+        let mut filter = CardFilter {
+            title: Some("acme".to_string()),
+            phase: Some("backlog".to_string()),
+            ..CardFilter::default()
+        };
+        assert!(filter.matches(&card()));
+        filter.phase = Some("3001".to_string());
+        assert!(filter.matches(&card()));
+        filter.phase = Some("Won".to_string());
+        assert!(!filter.matches(&card()));
+    }
+
+    #[test]
+    fn labels_and_assignees_need_any_of_the_wanted() {
+        let mut filter = CardFilter {
+            labels: vec!["Blocked".to_string(), "urgent".to_string()],
+            assignees: vec!["7".to_string()],
+            ..CardFilter::default()
+        };
+        assert!(filter.matches(&card()));
+        filter.assignees = vec!["Bruno Lima".to_string()];
+        assert!(!filter.matches(&card()));
+    }
+
+    #[test]
+    fn date_ranges_include_both_ends_in_the_card_offset() {
+        let mut filter = CardFilter {
+            created: DateRange {
+                from: day("2020-03-02"),
+                to: day("2020-03-02"),
+            },
+            ..CardFilter::default()
+        };
+        assert!(filter.matches(&card()));
+        filter.created.from = day("2020-03-03");
+        assert!(!filter.matches(&card()));
+        // Cards without the date only pass when the range is unset
+        filter.created = DateRange::default();
+        filter.due.to = day("2030-01-01");
+        assert!(!filter.matches(&card()));
+    }
+
+    #[test]
+    fn fields_match_by_id_or_name() {
+        let mut filter = CardFilter {
+            fields: vec![("customer".to_string(), "acme".to_string())],
+            ..CardFilter::default()
+        };
+        assert!(filter.matches(&card()));
+        filter.fields = vec![("Customer".to_string(), "Globex".to_string())];
+        assert!(!filter.matches(&card()));
+        filter.fields = vec![("Region".to_string(), "ACME".to_string())];
+        assert!(!filter.matches(&card()));
+    }
+
+    #[test]
+    fn only_numeric_ids_are_searched_by_pipefy() {
+        let filter = CardFilter {
+            labels: vec!["501".to_string()],
+            assignees: vec!["7".to_string(), "Ana".to_string()],
+            ..CardFilter::default()
+        };
+        let search = filter.search();
+        assert_eq!(search.label_ids, vec!["501".to_string()]);
+        assert!(search.assignee_ids.is_empty());
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Stands in for the API key in everything that is logged or traced.
+pub const REDACTED_AUTHORIZATION: &str = "Bearer [REDACTED]";
+
+/// One request and what came back. Trace files hold one per line, as JSON.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Exchange {
+    pub time: String,
+    pub url: String,
+    pub authorization: String,
+    pub query: String,
+    pub variables: Value,
+    pub status: Option<u16>,
+    pub duration_ms: u64,
+    /// The body as JSON, or as a string when it isn't JSON.
+    pub response: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// Writes exchanges to a trace file, readable only by its owner since it
+/// holds whole responses. Clones write to the same file.
+#[derive(Clone, Debug)]
+pub struct Tracer {
+    file: Arc<Mutex<File>>,
+}
+
+impl Exchange {
+    /// A one line summary, for `-v`.
+    pub fn summary(&self) -> String {
+        let outcome = match (self.status, &self.error) {
+            (Some(status), _) => status.to_string(),
+            (None, Some(error)) => error.to_string(),
+            (None, None) => "no response".to_string(),
+        };
+        format!("POST {} {} in {}ms", self.url, outcome, self.duration_ms)
+    }
+}
+
+impl Tracer {
+    pub fn create(path: &Path) -> io::Result<Tracer> {
+        Ok(Tracer {
+            file: Arc::new(Mutex::new(private_file(path)?)),
+        })
+    }
+
+    pub fn record(&self, exchange: &Exchange) -> io::Result<()> {
+        let mut line = serde_json::to_string(exchange)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(line.as_bytes())
+    }
+}
+
+/// Serves recorded exchanges instead of reaching the network. Identical
+/// requests get the recorded responses in order, the last one repeating.
+#[derive(Debug)]
+pub struct Replay {
+    exchanges: Mutex<HashMap<String, VecDeque<Exchange>>>,
+}
+
+impl Replay {
+    /// Loads a trace file, or every `.jsonl` file of a directory.
+    pub fn load(path: &Path) -> io::Result<Replay> {
+        let mut files = Vec::new();
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let file = entry?.path();
+                if file
+                    .extension()
+                    .map_or(false, |extension| extension == "jsonl")
+                {
+                    files.push(file);
+                }
+            }
+            files.sort();
+        } else {
+            files.push(path.to_path_buf());
+        }
+        let mut exchanges: HashMap<String, VecDeque<Exchange>> = HashMap::new();
+        for file in files {
+            for line in BufReader::new(File::open(file)?).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let exchange: Exchange = serde_json::from_str(&line)?;
+                if exchange.status.is_some() {
+                    exchanges
+                        .entry(replay_key(&exchange.query, &exchange.variables))
+                        .or_default()
+                        .push_back(exchange);
+                }
+            }
+        }
+        Ok(Replay {
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+
+    pub fn reply(&self, query: &str, variables: &Value) -> Option<Exchange> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let recorded = exchanges.get_mut(&replay_key(query, variables))?;
+        if recorded.len() > 1 {
+            recorded.pop_front()
+        } else {
+            recorded.front().cloned()
+        }
+    }
+}
+
+#[cfg(unix)]
+fn private_file(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files, an older trace may be readable
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn private_file(path: &Path) -> io::Result<File> {
+    File::create(path)
+}
+
+/// serde_json keeps object keys sorted, so equal variables print the same.
+fn replay_key(query: &str, variables: &Value) -> String {
+    format!("{}\n{}", query, variables)
+}
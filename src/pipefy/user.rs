@@ -1,7 +1,6 @@
-use crate::graphql::me_query;
-use dialoguer::{theme::ColorfulTheme, PasswordInput};
+use crate::client::{Endpoint, PipefyClient};
+use crate::error::PipefyError;
 use serde::{Deserialize, Serialize};
-use std::{error, fmt};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct User {
@@ -15,26 +14,6 @@ pub struct UserInfo {
     pub id: u32,
 }
 
-#[derive(Debug, Clone)]
-struct InvalidAPIKey;
-
-impl InvalidAPIKey {
-    fn new() -> InvalidAPIKey {
-        InvalidAPIKey {}
-    }
-}
-
-impl fmt::Display for InvalidAPIKey {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "InvalidAPIKey used")
-    }
-}
-impl error::Error for InvalidAPIKey {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        None
-    }
-}
-
 impl ::std::default::Default for User {
     fn default() -> Self {
         Self {
@@ -47,38 +26,8 @@ impl ::std::default::Default for User {
     }
 }
 
-pub fn test_existing_api_key(user: User) -> User {
-    match test_api_key(user.api_key) {
-        Ok(user) => user,
-        Err(_) => {
-            println!("Your API key is invalid, please update it");
-            get_working_api_key()
-        }
-    }
-}
-pub fn get_working_api_key() -> User {
-    let api_key = get_api_key();
-    match test_api_key(api_key) {
-        Ok(user) => user,
-        Err(_) => {
-            println!("Invalid API key, please try again");
-            get_working_api_key()
-        }
-    }
-}
-
-fn get_api_key() -> String {
-    let new_api_key = PasswordInput::with_theme(&ColorfulTheme::default())
-        .with_prompt("Your API key is not defined, please type it")
-        .interact()
-        .unwrap();
-    new_api_key.into()
-}
-
-fn test_api_key(api_key: String) -> Result<User, InvalidAPIKey> {
-    let result = me_query(&api_key);
-    match result {
-        Ok(api_key) => Ok(api_key),
-        Err(_) => Err(InvalidAPIKey::new()),
-    }
+/// Checks an API key against Pipefy and tells who it belongs to.
+pub fn verify(api_key: String, endpoint: &Endpoint) -> Result<User, PipefyError> {
+    let info = PipefyClient::new(&api_key, endpoint).me()?;
+    Ok(User { api_key, info })
 }
@@ -0,0 +1,341 @@
+use crate::graphql::{Card, Pipe};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+/// The cards of a pipe at one point in time.
+pub struct Snapshot {
+    cards: BTreeMap<usize, Card>,
+    done_phases: HashSet<usize>,
+    phase_names: BTreeMap<usize, String>,
+}
+
+/// Something that changed in a pipe between two snapshots.
+#[derive(Serialize, Debug, Clone)]
+pub struct Event {
+    pub time: DateTime<Local>,
+    pub card_id: usize,
+    pub title: String,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Change {
+    Created {
+        phase: String,
+    },
+    Moved {
+        from: String,
+        to: String,
+    },
+    FieldChanged {
+        field: String,
+        from: String,
+        to: String,
+    },
+    Done {
+        phase: String,
+    },
+    Removed,
+}
+
+impl Snapshot {
+    pub fn new(pipe: &Pipe, cards: Vec<Card>) -> Snapshot {
+        Snapshot {
+            cards: cards.into_iter().map(|card| (card.id, card)).collect(),
+            done_phases: pipe
+                .phases
+                .iter()
+                .filter(|phase| phase.done)
+                .map(|phase| phase.id)
+                .collect(),
+            phase_names: pipe
+                .phases
+                .iter()
+                .map(|phase| (phase.id, phase.name.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn card_count(&self) -> usize {
+        self.cards.len()
+    }
+
+    /// What happened to get from `self` to `current`, ordered by card id.
+    pub fn diff(&self, current: &Snapshot) -> Vec<Event> {
+        let time = Local::now();
+        let mut events = Vec::new();
+        let mut push = |card: &Card, change: Change| {
+            events.push(Event {
+                time,
+                card_id: card.id,
+                title: card.title.to_string(),
+                change,
+            })
+        };
+        for (id, card) in &current.cards {
+            let previous = match self.cards.get(id) {
+                Some(previous) => previous,
+                None => {
+                    push(
+                        card,
+                        Change::Created {
+                            phase: current.phase_name(card),
+                        },
+                    );
+                    continue;
+                }
+            };
+            let phase_id = |card: &Card| card.current_phase.as_ref().map(|phase| phase.id);
+            if phase_id(previous) != phase_id(card) {
+                push(
+                    card,
+                    Change::Moved {
+                        from: self.phase_name(previous),
+                        to: current.phase_name(card),
+                    },
+                );
+                if phase_id(card).map_or(false, |id| current.done_phases.contains(&id)) {
+                    push(
+                        card,
+                        Change::Done {
+                            phase: current.phase_name(card),
+                        },
+                    );
+                }
+            }
+            if previous.title != card.title {
+                push(
+                    card,
+                    Change::FieldChanged {
+                        field: "title".to_string(),
+                        from: previous.title.to_string(),
+                        to: card.title.to_string(),
+                    },
+                );
+            }
+            for (field, from, to) in changed_fields(previous, card) {
+                push(card, Change::FieldChanged { field, from, to });
+            }
+        }
+        for (id, card) in &self.cards {
+            if !current.cards.contains_key(id) {
+                push(card, Change::Removed);
+            }
+        }
+        events
+    }
+
+    fn phase_name(&self, card: &Card) -> String {
+        card.current_phase.as_ref().map_or(String::new(), |phase| {
+            self.phase_names
+                .get(&phase.id)
+                .map_or(phase.name.to_string(), |name| name.to_string())
+        })
+    }
+}
+
+/// Fields whose value differs, as (name, old value, new value).
+fn changed_fields(previous: &Card, current: &Card) -> Vec<(String, String, String)> {
+    let values = |card: &Card| {
+        card.fields
+            .iter()
+            .map(|field| {
+                (
+                    field.id.to_string(),
+                    (field.name.to_string(), field.value.to_string()),
+                )
+            })
+            .collect::<BTreeMap<String, (String, String)>>()
+    };
+    let before = values(previous);
+    let after = values(current);
+    let ids: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    ids.into_iter()
+        .filter_map(|id| {
+            let old = before.get(id);
+            let new = after.get(id);
+            let name = new.or(old).map(|(name, _)| name.to_string())?;
+            let old = old.map_or(String::new(), |(_, value)| value.to_string());
+            let new = new.map_or(String::new(), |(_, value)| value.to_string());
+            if old == new {
+                None
+            } else {
+                Some((name, old, new))
+            }
+        })
+        .collect()
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} #{} {}: ",
+            self.time.format("%H:%M:%S"),
+            self.card_id,
+            self.title
+        )?;
+        match &self.change {
+            Change::Created { phase } => write!(f, "created in {}", phase),
+            Change::Moved { from, to } => write!(f, "moved from {} to {}", from, to),
+            Change::FieldChanged { field, from, to } => {
+                write!(f, "{} changed from \"{}\" to \"{}\"", field, from, to)
+            }
+            Change::Done { phase } => write!(f, "done in {}", phase),
+            Change::Removed => write!(f, "removed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pipe() -> Pipe {
+        serde_json::from_value(json!({
+            "id": "301",
+            "name": "Sales",
+            "phases": [
+                {"id": "3001", "name": "Backlog", "cards_count": 0, "description": "", "done": false},
+                {"id": "3002", "name": "Negotiation", "cards_count": 0, "description": "", "done": false},
+                {"id": "3003", "name": "Won", "cards_count": 0, "description": "", "done": true}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn card(id: &str, title: &str, phase: &str, customer: &str) -> Card {
+        serde_json::from_value(json!({
+            "id": id,
+            "title": title,
+            "url": null,
+            "current_phase": {"id": phase, "name": "stale name"},
+            "fields": [{
+                "name": "Customer",
+                "value": customer,
+                "field": {"id": "customer", "type": "short_text"}
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn changes(before: Vec<Card>, after: Vec<Card>) -> Vec<(usize, Change)> {
+        let pipe = pipe();
+        Snapshot::new(&pipe, before)
+            .diff(&Snapshot::new(&pipe, after))
+            .into_iter()
+            .map(|event| (event.card_id, event.change))
+            .collect()
+    }
+
+    #[test]
+    fn nothing_changed_means_no_events() {
+        let cards = vec![card("1", "ACME", "3001", "ACME")];
+        assert!(changes(cards.clone(), cards).is_empty());
+    }
+
+    #[test]
+    fn created_and_removed_cards() {
+        let events = changes(
+            vec![card("1", "ACME", "3001", "ACME")],
+            vec![card("2", "Globex", "3002", "Globex")],
+        );
+        assert_eq!(
+            events,
+            vec![
+                (
+                    2,
+                    Change::Created {
+                        phase: "Negotiation".to_string()
+                    }
+                ),
+                (1, Change::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn moving_into_a_done_phase_is_also_done() {
+        let events = changes(
+            vec![
+                card("1", "ACME", "3001", "ACME"),
+                card("2", "Globex", "3001", "Globex"),
+            ],
+            vec![
+                card("1", "ACME", "3003", "ACME"),
+                card("2", "Globex", "3002", "Globex"),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![
+                (
+                    1,
+                    Change::Moved {
+                        from: "Backlog".to_string(),
+                        to: "Won".to_string()
+                    }
+                ),
+                (
+                    1,
+                    Change::Done {
+                        phase: "Won".to_string()
+                    }
+                ),
+                (
+                    2,
+                    Change::Moved {
+                        from: "Backlog".to_string(),
+                        to: "Negotiation".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn title_and_field_changes() {
+        let events = changes(
+            vec![card("1", "ACME", "3001", "ACME")],
+            vec![card("1", "ACME renewal", "3001", "ACME Corp")],
+        );
+        assert_eq!(
+            events,
+            vec![
+                (
+                    1,
+                    Change::FieldChanged {
+                        field: "title".to_string(),
+                        from: "ACME".to_string(),
+                        to: "ACME renewal".to_string()
+                    }
+                ),
+                (
+                    1,
+                    Change::FieldChanged {
+                        field: "Customer".to_string(),
+                        from: "ACME".to_string(),
+                        to: "ACME Corp".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn events_serialize_flat_with_their_kind() {
+        let events = Snapshot::new(&pipe(), Vec::new()).diff(&Snapshot::new(
+            &pipe(),
+            vec![card("1", "ACME", "3001", "ACME")],
+        ));
+        let json = serde_json::to_value(&events[0]).unwrap();
+        assert_eq!(json["event"], "created");
+        assert_eq!(json["card_id"], 1);
+        assert_eq!(json["phase"], "Backlog");
+    }
+}
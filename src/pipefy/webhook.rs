@@ -0,0 +1,218 @@
+use crate::graphql::{Card, PhaseRef};
+use chrono::{DateTime, Local};
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// What Pipefy posts to a webhook, like card.create, card.move,
+/// card.field_update, card.done or card.delete, made into the crate's models.
+#[derive(Serialize, Debug, Clone)]
+pub struct WebhookEvent {
+    pub time: DateTime<Local>,
+    pub action: String,
+    pub card: Card,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<PhaseRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<PhaseRef>,
+    /// Label of the field a card.field_update changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_value: Option<Value>,
+    /// Name of whoever caused the event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Payload {
+    data: Data,
+}
+
+// Ids come as numbers here, where the GraphQL API sends strings
+#[derive(Deserialize, Debug)]
+struct Data {
+    action: String,
+    card: PayloadCard,
+    from: Option<PayloadPhase>,
+    to: Option<PayloadPhase>,
+    field: Option<PayloadField>,
+    new_value: Option<Value>,
+    // created_by, moved_by, updated_by and so on, depending on the action
+    #[serde(flatten)]
+    rest: Map<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PayloadCard {
+    #[serde(deserialize_with = "any_id")]
+    id: usize,
+    #[serde(default)]
+    title: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PayloadPhase {
+    #[serde(deserialize_with = "any_id")]
+    id: usize,
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PayloadField {
+    #[serde(alias = "label_name")]
+    label: Option<String>,
+    id: Option<String>,
+}
+
+impl WebhookEvent {
+    pub fn parse(body: &str) -> Result<WebhookEvent, serde_json::Error> {
+        let data = serde_json::from_str::<Payload>(body)?.data;
+        let phase = |phase: PayloadPhase| PhaseRef {
+            id: phase.id,
+            name: phase.name,
+        };
+        let from = data.from.map(phase);
+        let to = data.to.map(phase);
+        let by = data
+            .rest
+            .iter()
+            .find(|(key, _)| key.ends_with("_by"))
+            .and_then(|(_, actor)| actor.get("name"))
+            .and_then(|name| name.as_str())
+            .map(|name| name.to_string());
+        Ok(WebhookEvent {
+            time: Local::now(),
+            action: data.action,
+            card: Card {
+                title: data.card.title,
+                id: data.card.id,
+                url: None,
+                current_phase: to.clone(),
+                created_at: None,
+                updated_at: None,
+                due_date: None,
+                labels: Vec::new(),
+                assignees: Vec::new(),
+                fields: Vec::new(),
+            },
+            from,
+            to,
+            field: data.field.and_then(|field| field.label.or(field.id)),
+            new_value: data.new_value,
+            by,
+        })
+    }
+}
+
+fn any_id<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Number(number) => number
+            .as_u64()
+            .map(|id| id as usize)
+            .ok_or_else(|| de::Error::custom(format!("invalid id {}", number))),
+        Value::String(id) => id.parse().map_err(de::Error::custom),
+        other => Err(de::Error::custom(format!("invalid id {}", other))),
+    }
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} #{} {}",
+            self.time.format("%H:%M:%S"),
+            self.action,
+            self.card.id,
+            self.card.title
+        )?;
+        if let (Some(from), Some(to)) = (&self.from, &self.to) {
+            write!(f, ": {} to {}", from.name, to.name)?;
+        }
+        if let Some(field) = &self.field {
+            let value = self.new_value.as_ref().map_or(String::new(), |value| {
+                value
+                    .as_str()
+                    .map_or(value.to_string(), |value| value.to_string())
+            });
+            write!(f, ": {} set to \"{}\"", field, value)?;
+        }
+        if let Some(by) = &self.by {
+            write!(f, " by {}", by)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_move_with_numeric_ids() {
+        let event = WebhookEvent::parse(
+            r#"{"data": {
+                "action": "card.move",
+                "from": {"id": 3001, "name": "Backlog"},
+                "to": {"id": 3002, "name": "Negotiation"},
+                "moved_by": {"id": 7, "name": "Ana Souza", "email": "ana@example.com"},
+                "card": {"id": 1001, "title": "ACME renewal", "pipe_id": "301"}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(event.action, "card.move");
+        assert_eq!(event.card.id, 1001);
+        assert_eq!(event.from.unwrap().name, "Backlog");
+        assert_eq!(event.to.unwrap().id, 3002);
+        assert_eq!(event.card.current_phase.unwrap().name, "Negotiation");
+        assert_eq!(event.by, Some("Ana Souza".to_string()));
+    }
+
+    #[test]
+    fn field_update_with_string_ids() {
+        let event = WebhookEvent::parse(
+            r#"{"data": {
+                "action": "card.field_update",
+                "field": {"id": "customer", "label_name": "Customer"},
+                "new_value": "ACME Corp",
+                "updated_by": {"id": 8, "name": "Bruno Lima"},
+                "card": {"id": "1001", "title": "ACME renewal"}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(event.card.id, 1001);
+        assert_eq!(event.field, Some("Customer".to_string()));
+        assert_eq!(
+            event.new_value,
+            Some(Value::String("ACME Corp".to_string()))
+        );
+        assert!(event.to_string().ends_with(
+            "card.field_update #1001 ACME renewal: Customer set to \"ACME Corp\" by Bruno Lima"
+        ));
+    }
+
+    #[test]
+    fn bare_card_create() {
+        let event =
+            WebhookEvent::parse(r#"{"data": {"action": "card.create", "card": {"id": 7}}}"#)
+                .unwrap();
+        assert_eq!(event.card.id, 7);
+        assert_eq!(event.card.title, "");
+        assert!(event.from.is_none() && event.field.is_none() && event.by.is_none());
+    }
+
+    #[test]
+    fn other_payloads_are_rejected() {
+        assert!(WebhookEvent::parse(r#"{"ping": true}"#).is_err());
+        assert!(WebhookEvent::parse(
+            r#"{"data": {"action": "card.create", "card": {"id": "abc"}}}"#
+        )
+        .is_err());
+        assert!(WebhookEvent::parse("not json").is_err());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::config::{AuthStatus, ProfileSummary};
+use crate::graphql::{Card, Member, OrgPipe, Organization, Phase, Pipe};
+use prettyprint::PrettyPrinter;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    Pretty,
+    Plain,
+    Table,
+    Csv,
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Clone)]
+pub struct InvalidOutput(String);
+
+impl fmt::Display for InvalidOutput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid output format {}, expected one of pretty, plain, table, csv, json, yaml",
+            self.0
+        )
+    }
+}
+
+impl FromStr for Output {
+    type Err = InvalidOutput;
+
+    fn from_str(s: &str) -> Result<Output, InvalidOutput> {
+        match s {
+            "pretty" => Ok(Output::Pretty),
+            "plain" => Ok(Output::Plain),
+            "table" => Ok(Output::Table),
+            "csv" => Ok(Output::Csv),
+            "json" => Ok(Output::Json),
+            "yaml" => Ok(Output::Yaml),
+            _ => Err(InvalidOutput(s.to_string())),
+        }
+    }
+}
+
+/// Anything that can be laid out as a row of a table or a CSV line.
+pub trait Tabular {
+    fn headers() -> Vec<&'static str>;
+    fn row(&self) -> Vec<String>;
+}
+
+impl Tabular for Card {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "title", "url", "phase", "fields"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.title.to_string(),
+            self.url.clone().unwrap_or_default(),
+            self.current_phase
+                .as_ref()
+                .map_or(String::new(), |phase| phase.name.to_string()),
+            self.fields_summary(),
+        ]
+    }
+}
+
+impl Tabular for Phase {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "name", "cards_count", "done", "description"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.name.to_string(),
+            self.cards_count.to_string(),
+            self.done.to_string(),
+            self.description.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Tabular for Pipe {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "name"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.id.to_string(), self.name.to_string()]
+    }
+}
+
+impl Tabular for Organization {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "name"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![self.id.to_string(), self.name.to_string()]
+    }
+}
+
+impl Tabular for OrgPipe {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "name", "phases", "cards", "public", "member"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.name.to_string(),
+            self.phases_count.to_string(),
+            self.cards_count.to_string(),
+            self.public.to_string(),
+            self.member.to_string(),
+        ]
+    }
+}
+
+impl Tabular for Member {
+    fn headers() -> Vec<&'static str> {
+        vec!["id", "name", "role"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.user.id.to_string(),
+            self.user.name.to_string(),
+            self.role_name.to_string(),
+        ]
+    }
+}
+
+impl Tabular for ProfileSummary {
+    fn headers() -> Vec<&'static str> {
+        vec![
+            "name",
+            "current",
+            "endpoint",
+            "default_org",
+            "default_pipe",
+            "user",
+        ]
+    }
+
+    fn row(&self) -> Vec<String> {
+        let optional = |id: Option<usize>| id.map_or(String::new(), |id| id.to_string());
+        vec![
+            self.name.to_string(),
+            self.current.to_string(),
+            self.endpoint.to_string(),
+            optional(self.default_org),
+            optional(self.default_pipe),
+            self.user.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Tabular for AuthStatus {
+    fn headers() -> Vec<&'static str> {
+        vec!["profile", "endpoint", "user", "user_id", "validated_at"]
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.profile.to_string(),
+            self.endpoint.to_string(),
+            self.user.clone().unwrap_or_default(),
+            self.user_id.map_or(String::new(), |id| id.to_string()),
+            self.validated_at.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+pub fn one<T>(output: Output, title: &str, item: &T) -> Result<(), Box<Error>>
+where
+    T: Serialize + Tabular + Display,
+{
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        Output::Yaml => println!("{}", serde_yaml::to_string(item)?),
+        Output::Csv => csv(std::slice::from_ref(item))?,
+        Output::Table => table(std::slice::from_ref(item)),
+        Output::Plain => println!("{}", item),
+        Output::Pretty => pretty(title, item.to_string())?,
+    }
+    Ok(())
+}
+
+pub fn list<T>(output: Output, title: &str, items: &[T]) -> Result<(), Box<Error>>
+where
+    T: Serialize + Tabular + Display,
+{
+    match output {
+        Output::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        Output::Yaml => println!("{}", serde_yaml::to_string(items)?),
+        Output::Csv => csv(items)?,
+        Output::Table => table(items),
+        Output::Plain => {
+            for item in items {
+                println!("{}\n", item);
+            }
+        }
+        Output::Pretty => {
+            let printable_items = items
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<String>>()
+                .join("\n\n");
+            pretty(title, printable_items)?
+        }
+    }
+    Ok(())
+}
+
+/// Prints pages as they arrive. Formats that need every row up front
+/// (pretty, table and yaml) wait for the last page before printing.
+pub fn stream<T, I, E>(output: Output, title: &str, pages: I) -> Result<(), Box<Error>>
+where
+    T: Serialize + Tabular + Display,
+    I: Iterator<Item = Result<Vec<T>, E>>,
+    E: Error + 'static,
+{
+    match output {
+        Output::Json => {
+            println!("[");
+            let mut first = true;
+            for page in pages {
+                for item in page? {
+                    if !first {
+                        println!(",");
+                    }
+                    print!("{}", serde_json::to_string(&item)?);
+                    first = false;
+                }
+            }
+            println!("\n]");
+        }
+        Output::Csv => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            writer.write_record(&T::headers())?;
+            for page in pages {
+                for item in page? {
+                    writer.write_record(&item.row())?;
+                }
+                writer.flush()?;
+            }
+        }
+        Output::Plain => {
+            for page in pages {
+                for item in page? {
+                    println!("{}\n", item);
+                }
+            }
+        }
+        Output::Pretty | Output::Table | Output::Yaml => {
+            let mut items = Vec::new();
+            for page in pages {
+                items.extend(page?);
+            }
+            list(output, title, &items)?;
+        }
+    }
+    Ok(())
+}
+
+fn pretty(title: &str, body: String) -> Result<(), Box<Error>> {
+    let print = PrettyPrinter::default()
+        .language("rust")
+        .grid(true)
+        .line_numbers(true)
+        .build()?;
+    print.string_with_header(body, title.to_string())?;
+    Ok(())
+}
+
+fn csv<T: Tabular>(items: &[T]) -> Result<(), Box<Error>> {
+    let mut writer = csv::Writer::from_writer(io::stdout());
+    writer.write_record(&T::headers())?;
+    for item in items {
+        writer.write_record(&item.row())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn table<T: Tabular>(items: &[T]) {
+    let headers: Vec<String> = T::headers().iter().map(|h| h.to_uppercase()).collect();
+    let rows: Vec<Vec<String>> = items.iter().map(|item| item.row()).collect();
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    print_row(&headers, &widths);
+    for row in &rows {
+        print_row(row, &widths);
+    }
+}
+
+fn print_row(cells: &[String], widths: &[usize]) {
+    let line = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect::<Vec<String>>()
+        .join("  ");
+    println!("{}", line.trim_end());
+}
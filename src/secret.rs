@@ -0,0 +1,267 @@
+use dialoguer::{theme::ColorfulTheme, PasswordInput};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::{env, error, fmt, io};
+
+const SEALED_PREFIX: &str = "sealed:v1:";
+const PASSPHRASE_VARIABLE: &str = "PIPE_CLI_PASSPHRASE";
+const SECRET_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Where the secret that encrypts the stored API keys comes from.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// Random bytes kept in a file only the user can read.
+    SecretFile,
+    /// A passphrase typed when needed, or set in `PIPE_CLI_PASSPHRASE`.
+    Passphrase,
+}
+
+/// Encrypts and decrypts API keys with a secret that is never stored in
+/// the config itself.
+pub struct Keyring {
+    secret: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum SecretError {
+    SecretFile(PathBuf, io::Error),
+    NoPassphrase,
+    Prompt(io::Error),
+    Random,
+    Unreadable,
+}
+
+impl ::std::default::Default for KeySource {
+    fn default() -> Self {
+        KeySource::SecretFile
+    }
+}
+
+impl Keyring {
+    /// Opens the keyring the stored keys were sealed with. A missing secret
+    /// file is created, since no key can have been sealed with it yet.
+    pub fn open(source: &KeySource, secret_file: &Path) -> Result<Keyring, SecretError> {
+        let secret = match source {
+            KeySource::SecretFile => read_secret_file(secret_file)?,
+            KeySource::Passphrase => passphrase(false)?,
+        };
+        Ok(Keyring { secret })
+    }
+
+    /// Like `open`, but a typed passphrase has to be repeated, as nothing
+    /// sealed with it exists yet to tell a typo apart.
+    pub fn create(source: &KeySource, secret_file: &Path) -> Result<Keyring, SecretError> {
+        let secret = match source {
+            KeySource::SecretFile => read_secret_file(secret_file)?,
+            KeySource::Passphrase => passphrase(true)?,
+        };
+        Ok(Keyring { secret })
+    }
+
+    pub fn seal(&self, plaintext: &str) -> Result<String, SecretError> {
+        let rng = SystemRandom::new();
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill(&mut salt)
+            .and_then(|_| rng.fill(&mut nonce))
+            .map_err(|_| SecretError::Random)?;
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.key(&salt)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .map_err(|_| SecretError::Random)?;
+        let mut blob = salt.to_vec();
+        blob.extend_from_slice(&nonce);
+        blob.extend(sealed);
+        Ok(format!("{}{}", SEALED_PREFIX, base64::encode(&blob)))
+    }
+
+    pub fn unseal(&self, sealed: &str) -> Result<String, SecretError> {
+        let encoded = sealed
+            .get(SEALED_PREFIX.len()..)
+            .filter(|_| is_sealed(sealed))
+            .ok_or(SecretError::Unreadable)?;
+        let mut blob = base64::decode(encoded).map_err(|_| SecretError::Unreadable)?;
+        if blob.len() < SALT_LEN + NONCE_LEN {
+            return Err(SecretError::Unreadable);
+        }
+        let mut ciphertext = blob.split_off(SALT_LEN + NONCE_LEN);
+        let (salt, nonce) = blob.split_at(SALT_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| SecretError::Unreadable)?;
+        let plaintext = self
+            .key(salt)
+            .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+            .map_err(|_| SecretError::Unreadable)?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| SecretError::Unreadable)
+    }
+
+    fn key(&self, salt: &[u8]) -> LessSafeKey {
+        let mut key = [0u8; KEY_LEN];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+            salt,
+            &self.secret,
+            &mut key,
+        );
+        LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &key).unwrap())
+    }
+}
+
+/// Whether a stored API key was sealed, as opposed to an old plaintext one.
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+fn read_secret_file(path: &Path) -> Result<Vec<u8>, SecretError> {
+    let to_error = |e| SecretError::SecretFile(path.to_path_buf(), e);
+    match fs::read(path) {
+        Ok(secret) => Ok(secret),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut secret = vec![0u8; SECRET_LEN];
+            SystemRandom::new()
+                .fill(&mut secret)
+                .map_err(|_| SecretError::Random)?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(to_error)?;
+            }
+            private_file(path)
+                .and_then(|mut file| file.write_all(&secret))
+                .map_err(to_error)?;
+            Ok(secret)
+        }
+        Err(e) => Err(to_error(e)),
+    }
+}
+
+#[cfg(unix)]
+fn private_file(path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn private_file(path: &Path) -> io::Result<fs::File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+fn passphrase(confirm: bool) -> Result<Vec<u8>, SecretError> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VARIABLE) {
+        if !passphrase.is_empty() {
+            return Ok(passphrase.into_bytes());
+        }
+    }
+    if !atty::is(atty::Stream::Stdin) {
+        return Err(SecretError::NoPassphrase);
+    }
+    let theme = ColorfulTheme::default();
+    let mut input = PasswordInput::with_theme(&theme);
+    input.with_prompt("Passphrase of the stored API keys");
+    if confirm {
+        input.with_confirmation("Repeat the passphrase", "The passphrases don't match");
+    }
+    let passphrase = input.interact().map_err(SecretError::Prompt)?;
+    Ok(passphrase.into_bytes())
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecretError::SecretFile(path, e) => {
+                write!(f, "Could not use the secret file {}: {}", path.display(), e)
+            }
+            SecretError::NoPassphrase => write!(
+                f,
+                "The API keys are encrypted with a passphrase, set {} or run pipe_cli in a terminal",
+                PASSPHRASE_VARIABLE
+            ),
+            SecretError::Prompt(e) => write!(f, "Could not read the passphrase: {}", e),
+            SecretError::Random => write!(f, "Could not encrypt the API key"),
+            SecretError::Unreadable => write!(
+                f,
+                "Could not decrypt the stored API key, is the passphrase or secret file right?"
+            ),
+        }
+    }
+}
+
+impl error::Error for SecretError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SecretError::SecretFile(_, e) | SecretError::Prompt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secret_file(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("pipe_cli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn sealed_keys_round_trip() {
+        let path = secret_file("round-trip");
+        let keyring = Keyring::open(&KeySource::SecretFile, &path).unwrap();
+        let sealed = keyring.seal("api-key").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("api-key"));
+        assert_ne!(sealed, keyring.seal("api-key").unwrap());
+        let reopened = Keyring::open(&KeySource::SecretFile, &path).unwrap();
+        assert_eq!(reopened.unseal(&sealed).unwrap(), "api-key");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn another_secret_can_not_unseal() {
+        let sealed = Keyring {
+            secret: b"one secret".to_vec(),
+        }
+        .seal("api-key")
+        .unwrap();
+        let other = Keyring {
+            secret: b"another secret".to_vec(),
+        };
+        match other.unseal(&sealed) {
+            Err(SecretError::Unreadable) => (),
+            other => panic!("expected Unreadable, got {:?}", other),
+        }
+        match other.unseal("api-key") {
+            Err(SecretError::Unreadable) => (),
+            other => panic!("expected Unreadable, got {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_secret_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = secret_file("private");
+        Keyring::open(&KeySource::SecretFile, &path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+}
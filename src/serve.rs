@@ -0,0 +1,138 @@
+use crate::client::PipefyClient;
+use crate::render::Output;
+use crate::webhook::WebhookEvent;
+use log::warn;
+use ring::constant_time;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use tiny_http::{Method, Request, Response, Server};
+
+/// Matches every action in `--on`.
+const ANY_ACTION: &str = "*";
+/// Header that carries `--secret`, as set on the webhook in Pipefy.
+const SECRET_HEADER: &str = "X-Pipe-Cli-Secret";
+/// Card events are a few KB, anything much bigger is not from Pipefy.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Receives Pipefy webhooks until interrupted. Every event is printed, and
+/// handed to the hooks registered for its action as JSON on their stdin.
+pub fn run(
+    client: &PipefyClient,
+    output: Output,
+    bind: &str,
+    port: u16,
+    hooks: &[(String, String)],
+    fetch: bool,
+    secret: Option<&str>,
+) -> Result<(), Box<Error>> {
+    let server = Server::http((bind, port)).map_err(|e| e.to_string())?;
+    eprintln!("Listening on http://{}:{}, Ctrl-C to stop", bind, port);
+    for mut request in server.incoming_requests() {
+        if *request.method() != Method::Post {
+            respond(request, 405, "Webhooks are POSTed");
+            continue;
+        }
+        if let Some(secret) = secret {
+            if !has_secret(&request, secret) {
+                warn!("Rejecting a webhook without the expected {}", SECRET_HEADER);
+                respond(request, 401, "Wrong or missing secret");
+                continue;
+            }
+        }
+        if request
+            .body_length()
+            .map_or(false, |length| length > MAX_BODY_BYTES)
+        {
+            respond(request, 413, "Body too large");
+            continue;
+        }
+        let mut body = String::new();
+        // One byte past the limit is enough to tell a chunked body is too big
+        if let Err(e) = request
+            .as_reader()
+            .take(MAX_BODY_BYTES as u64 + 1)
+            .read_to_string(&mut body)
+        {
+            respond(request, 400, &e.to_string());
+            continue;
+        }
+        if body.len() > MAX_BODY_BYTES {
+            respond(request, 413, "Body too large");
+            continue;
+        }
+        let mut event = match WebhookEvent::parse(&body) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Ignoring a webhook that is not a card event: {}", e);
+                respond(request, 400, &e.to_string());
+                continue;
+            }
+        };
+        // Answer before running the hooks, Pipefy does not wait for long
+        respond(request, 200, "");
+        if fetch {
+            match client.card(event.card.id) {
+                Ok(card) => event.card = card,
+                Err(e) => warn!("Could not fetch card {}: {}", event.card.id, e),
+            }
+        }
+        let json = serde_json::to_string(&event)?;
+        match output {
+            Output::Json => println!("{}", json),
+            _ => println!("{}", event),
+        }
+        for (_, command) in hooks
+            .iter()
+            .filter(|(action, _)| action == ANY_ACTION || *action == event.action)
+        {
+            if let Err(e) = run_hook(command, &event, &json) {
+                eprintln!("{}: {}", command, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn has_secret(request: &Request, secret: &str) -> bool {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(SECRET_HEADER))
+        .map_or(false, |header| {
+            constant_time::verify_slices_are_equal(
+                header.value.as_str().as_bytes(),
+                secret.as_bytes(),
+            )
+            .is_ok()
+        })
+}
+
+fn respond(request: Request, status: u16, message: &str) {
+    let response = Response::from_string(message).with_status_code(status);
+    if let Err(e) = request.respond(response) {
+        warn!("Could not answer the webhook: {}", e);
+    }
+}
+
+/// Runs a hook through the shell with the event on stdin, waiting for it so
+/// that hooks of consecutive events do not overlap.
+fn run_hook(command: &str, event: &WebhookEvent, json: &str) -> Result<(), Box<Error>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("PIPE_CLI_ACTION", &event.action)
+        .env("PIPE_CLI_CARD_ID", event.card.id.to_string())
+        .env("PIPE_CLI_CARD_TITLE", &event.card.title)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that does not read its stdin closes it early, which is fine
+        let _ = stdin.write_all(json.as_bytes());
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(format!("exited with {}", status).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,337 @@
+//! The client against recorded traces, see `--trace-file` and `--replay`.
+//! Fixtures are trace files: one JSON exchange per line.
+use pipe_cli::client::DEFAULT_ENDPOINT;
+use pipe_cli::field::CardFieldValue;
+use pipe_cli::graphql::{Card, FieldValue};
+use pipe_cli::search::CardFilter;
+use pipe_cli::trace::{Replay, Tracer};
+use pipe_cli::{Endpoint, HttpSettings, PipefyClient, PipefyError};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+fn endpoint(fixture: &str, retries: u32) -> Endpoint {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(fixture);
+    let replay = Replay::load(&path).unwrap();
+    let settings = HttpSettings {
+        retries: Some(retries),
+        ..HttpSettings::default()
+    };
+    Endpoint::new(DEFAULT_ENDPOINT, &settings)
+        .unwrap()
+        .with_replay(replay)
+}
+
+fn client(fixture: &str, retries: u32) -> PipefyClient {
+    PipefyClient::new("not-a-real-key", &endpoint(fixture, retries))
+}
+
+fn ids(cards: Vec<Card>) -> Vec<usize> {
+    cards.iter().map(|card| card.id).collect()
+}
+
+#[test]
+fn me_decodes_the_user() {
+    let me = client("me.jsonl", 0).me().unwrap();
+    assert_eq!((me.id, me.name.as_str()), (7, "Ana Souza"));
+}
+
+#[test]
+fn pipe_decodes_phases_with_and_without_description() {
+    let pipe = client("pipe.jsonl", 0).pipe(301).unwrap();
+    assert_eq!(pipe.name, "Sales");
+    let phases: Vec<(usize, &str, bool)> = pipe
+        .phases
+        .iter()
+        .map(|phase| (phase.id, phase.name.as_str(), phase.done))
+        .collect();
+    assert_eq!(
+        phases,
+        vec![
+            (3001, "Backlog", false),
+            (3002, "Negotiation", false),
+            (3003, "Won", true)
+        ]
+    );
+    assert_eq!(pipe.phases[0].description, Some("New leads".to_string()));
+    assert_eq!(pipe.phases[1].description, None);
+}
+
+#[test]
+fn phase_decodes_its_fields() {
+    let phase = client("phase.jsonl", 0).phase(3002).unwrap();
+    assert_eq!(phase.name, "Negotiation");
+    assert_eq!(phase.description, None);
+    assert!(!phase.done);
+    let fields: Vec<(&str, &str, bool)> = phase
+        .fields
+        .iter()
+        .map(|field| (field.id.as_str(), field.kind.as_str(), field.required))
+        .collect();
+    assert_eq!(
+        fields,
+        vec![("value", "currency", true), ("priority", "select", false)]
+    );
+    assert_eq!(phase.fields[1].options, vec!["Low", "High"]);
+}
+
+#[test]
+fn card_decodes_typed_fields() {
+    let card = client("card.jsonl", 0).card(1001).unwrap();
+    assert_eq!(card.title, "ACME renewal");
+    assert_eq!(card.current_phase.unwrap().name, "Backlog");
+    let values: Vec<&CardFieldValue> = card.fields.iter().map(|field| &field.value).collect();
+    assert_eq!(
+        values,
+        vec![
+            &CardFieldValue::ShortText("ACME".to_string()),
+            &CardFieldValue::Currency(1250.5),
+            &CardFieldValue::Checklist(vec!["Signed".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn organization_lists_members() {
+    let organization = client("organization.jsonl", 0).organization(10).unwrap();
+    assert_eq!(organization.name, "ACME Sales");
+    let members: Vec<(&str, &str)> = organization
+        .members
+        .iter()
+        .map(|member| (member.user.name.as_str(), member.role_name.as_str()))
+        .collect();
+    assert_eq!(
+        members,
+        vec![("Ana Souza", "admin"), ("Bruno Lima", "member")]
+    );
+}
+
+#[test]
+fn organization_pipes_flags_membership() {
+    let pipes = client("organization_pipes.jsonl", 0)
+        .organization_pipes(10)
+        .unwrap();
+    let summary: Vec<(&str, usize, bool)> = pipes
+        .iter()
+        .map(|pipe| (pipe.name.as_str(), pipe.phases_count, pipe.member))
+        .collect();
+    assert_eq!(summary, vec![("Sales", 3, true), ("Hiring", 1, false)]);
+}
+
+#[test]
+fn pipe_cards_follows_the_cursor() {
+    let client = client("pipe_cards.jsonl", 0);
+    let pages: Vec<Vec<usize>> = client
+        .pipe_cards(301, 2, None)
+        .map(|page| page.unwrap().iter().map(|card| card.id).collect())
+        .collect();
+    assert_eq!(pages, vec![vec![1001, 1002], vec![1003]]);
+}
+
+#[test]
+fn pipe_cards_stops_at_the_limit() {
+    let client = client("pipe_cards.jsonl", 0);
+    let cards: Vec<usize> = client
+        .pipe_cards(301, 2, Some(2))
+        .flat_map(|page| page.unwrap())
+        .map(|card| card.id)
+        .collect();
+    assert_eq!(cards, vec![1001, 1002]);
+}
+
+#[test]
+fn phase_cards_follows_the_cursor() {
+    let client = client("phase_cards.jsonl", 0);
+    let pages: Vec<Vec<usize>> = client
+        .phase_cards(3001, 2, None)
+        .map(|page| ids(page.unwrap()))
+        .collect();
+    assert_eq!(pages, vec![vec![1001, 1002], vec![1003]]);
+}
+
+#[test]
+fn search_cards_filters_every_page() {
+    let client = client("search_cards.jsonl", 0);
+    let filter = CardFilter {
+        title: Some("renewal".to_string()),
+        phase: Some("negotiation".to_string()),
+        ..CardFilter::default()
+    };
+    let pages: Vec<Vec<usize>> = client
+        .search_cards(301, &filter, 2, None)
+        .map(|page| ids(page.unwrap()))
+        .collect();
+    assert_eq!(pages, vec![vec![1001], vec![1005]]);
+}
+
+#[test]
+fn search_cards_stops_at_the_limit() {
+    let client = client("search_cards.jsonl", 0);
+    let filter = CardFilter {
+        title: Some("renewal".to_string()),
+        ..CardFilter::default()
+    };
+    let cards: Vec<usize> = client
+        .search_cards(301, &filter, 2, Some(3))
+        .flat_map(|page| ids(page.unwrap()))
+        .collect();
+    assert_eq!(cards, vec![1001, 1004, 1005]);
+    let cards: Vec<usize> = client
+        .search_cards(301, &filter, 2, Some(1))
+        .flat_map(|page| ids(page.unwrap()))
+        .collect();
+    assert_eq!(cards, vec![1001]);
+}
+
+#[test]
+fn search_cards_fetches_no_page_past_the_limit() {
+    let trace = std::env::temp_dir().join(format!("pipe_cli-search-{}.jsonl", process::id()));
+    let endpoint = endpoint("search_cards.jsonl", 0).with_tracer(Tracer::create(&trace).unwrap());
+    let client = PipefyClient::new("not-a-real-key", &endpoint);
+    let filter = CardFilter {
+        title: Some("renewal".to_string()),
+        ..CardFilter::default()
+    };
+    for limit in &[0, 2] {
+        let cards: Vec<Card> = client
+            .search_cards(301, &filter, 2, Some(*limit))
+            .flat_map(|page| page.unwrap())
+            .collect();
+        assert_eq!(cards.len(), *limit);
+    }
+    let requests = fs::read_to_string(&trace).unwrap().lines().count();
+    fs::remove_file(&trace).unwrap();
+    assert_eq!(requests, 1);
+}
+
+#[test]
+fn start_form_and_editable_fields() {
+    let client = client("forms.jsonl", 0);
+    let start_form: Vec<String> = client
+        .start_form_fields(301)
+        .unwrap()
+        .into_iter()
+        .map(|field| field.id)
+        .collect();
+    assert_eq!(start_form, vec!["customer", "contact_date", "products"]);
+    let editable: Vec<String> = client
+        .editable_fields(1001)
+        .unwrap()
+        .into_iter()
+        .map(|field| field.id)
+        .collect();
+    assert_eq!(
+        editable,
+        vec!["customer", "contact_date", "products", "value", "priority"]
+    );
+}
+
+#[test]
+fn move_targets_find_destinations_by_name_or_id() {
+    let targets = client("forms.jsonl", 0).move_targets(1001).unwrap();
+    assert_eq!(targets.name, "Backlog");
+    assert_eq!(targets.destination("won").unwrap().id, 3003);
+    assert_eq!(targets.destination("3002").unwrap().name, "Negotiation");
+    assert!(targets.destination("Backlog").is_none());
+}
+
+#[test]
+fn create_card_sends_the_fields() {
+    let fields = vec![FieldValue {
+        field_id: "customer".to_string(),
+        field_value: json!("Hooli"),
+    }];
+    let card = client("mutations.jsonl", 0)
+        .create_card(301, Some("Hooli expansion"), &fields)
+        .unwrap();
+    assert_eq!((card.id, card.title.as_str()), (1006, "Hooli expansion"));
+}
+
+#[test]
+fn move_card_returns_the_new_phase() {
+    let card = client("mutations.jsonl", 0).move_card(1001, 3002).unwrap();
+    assert_eq!(card.current_phase.unwrap().name, "Negotiation");
+}
+
+#[test]
+fn update_card_field_returns_the_new_value() {
+    let card = client("mutations.jsonl", 0)
+        .update_card_field(1001, "customer", json!("ACME Corp"))
+        .unwrap();
+    assert_eq!(
+        card.field("customer").unwrap().value,
+        CardFieldValue::ShortText("ACME Corp".to_string())
+    );
+}
+
+#[test]
+fn mutations_are_not_retried_on_a_bad_gateway() {
+    // The second recording would succeed, had the move been sent again
+    match client("mutations.jsonl", 3).move_card(1002, 3002) {
+        Err(PipefyError::HttpStatus(502)) => (),
+        other => panic!("expected HttpStatus(502), got {:?}", other),
+    }
+}
+
+#[test]
+fn queries_are_retried_on_a_bad_gateway() {
+    let phase = client("mutations.jsonl", 1).phase(3003).unwrap();
+    assert!(phase.done);
+}
+
+#[test]
+fn missing_card_is_not_found() {
+    match client("errors.jsonl", 0).card(404) {
+        Err(PipefyError::NotFound(what)) => assert_eq!(what, "card 404"),
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn graphql_errors_are_reported() {
+    let client = client("errors.jsonl", 0);
+    match client.card(405) {
+        Err(PipefyError::GraphQL(errors)) => {
+            assert_eq!(errors[0].message, "Field 'title' is invalid for this card")
+        }
+        other => panic!("expected GraphQL, got {:?}", other),
+    }
+    match client.card(403) {
+        Err(PipefyError::PermissionDenied(_)) => (),
+        other => panic!("expected PermissionDenied, got {:?}", other),
+    }
+}
+
+#[test]
+fn unauthorized_is_not_retried() {
+    match client("errors.jsonl", 3).pipe(401) {
+        Err(PipefyError::Unauthorized) => (),
+        other => panic!("expected Unauthorized, got {:?}", other),
+    }
+}
+
+#[test]
+fn rate_limit_fails_once_retries_run_out() {
+    match client("errors.jsonl", 0).pipe(429) {
+        Err(PipefyError::HttpStatus(429)) => (),
+        other => panic!("expected HttpStatus(429), got {:?}", other),
+    }
+}
+
+#[test]
+fn rate_limit_is_retried() {
+    let pipe = client("errors.jsonl", 1).pipe(302).unwrap();
+    assert_eq!(pipe.name, "Hiring");
+}
+
+#[test]
+fn unrecorded_requests_fail() {
+    match client("pipe.jsonl", 0).pipe(999) {
+        Err(PipefyError::Unrecorded(_)) => (),
+        other => panic!("expected Unrecorded, got {:?}", other),
+    }
+}